@@ -1,4 +1,7 @@
 const NO_MOVE: u64 = 0;
+pub const DARK_SQUARES: u64 = 0xaa55aa55aa55aa55;
+pub const FILE_A: u64 = 0x0101010101010101;
+pub const FILE_H: u64 = FILE_A << 7;
 
 lazy_static! {
     pub static ref PAWN_MOVES: [u64; 64] = init_pawn_moves();
@@ -8,8 +11,14 @@ lazy_static! {
     pub static ref ROOK_MOVES: [u64; 64] = init_rook_moves();
     pub static ref QUEEN_MOVES: [u64; 64] = init_queen_moves();
     pub static ref KING_MOVES: [u64; 64] = init_king_moves();
+    static ref RAYS: [[u64; 64]; 8] = init_rays();
 }
 
+// compass directions as (rank, file) steps; the first four move towards higher squares
+const DIRECTIONS: [(i32, i32); 8] = [(1, 0), (1, 1), (0, 1), (1, -1), (-1, 0), (-1, -1), (0, -1), (-1, 1)];
+const DIAGONALS: [usize; 4] = [1, 3, 5, 7];
+const LINES: [usize; 4] = [0, 2, 4, 6];
+
 fn init_pawn_moves() -> [u64; 64] {
     let mut moves: [u64; 64] = [0; 64];
     for sq in 0..64 {
@@ -170,6 +179,18 @@ fn init_king_move(sq: i32) -> u64 {
     n | ne | e | se | s | sw | w | nw
 }
 
+fn init_rays() -> [[u64; 64]; 8] {
+    let mut rays = [[0; 64]; 8];
+    for (dir, (rank_walk, file_walk)) in DIRECTIONS.iter().enumerate() {
+        for sq in 0..64 {
+            let (rank, file) = to_rank_file(sq as i32);
+            rays[dir][sq] = walk_to_edge(rank, file, *rank_walk, *file_walk);
+        }
+    }
+
+    rays
+}
+
 fn walk_to_edge(rank: i32, file: i32, rank_walk: i32, file_walk: i32) -> u64 {
     let (mut r, mut f) = (rank, file);
     let mut walk = NO_MOVE;
@@ -184,6 +205,61 @@ fn walk_to_edge(rank: i32, file: i32, rank_walk: i32, file_walk: i32) -> u64 {
     walk
 }
 
+// squares a bishop on the square attacks, up to and including the first piece in each direction
+pub fn bishop_attacks(sq: i32, occupied: u64) -> u64 {
+    DIAGONALS.iter().fold(0, |attacks, dir| attacks | ray_attacks(sq, occupied, *dir))
+}
+
+pub fn rook_attacks(sq: i32, occupied: u64) -> u64 {
+    LINES.iter().fold(0, |attacks, dir| attacks | ray_attacks(sq, occupied, *dir))
+}
+
+// squares from which a pawn of the given color attacks the square
+pub fn pawn_attackers(sq: i32, white: bool) -> u64 {
+    if white {
+        PAWN_ATTACKS[(sq ^ 56) as usize].swap_bytes()
+    } else {
+        PAWN_ATTACKS[sq as usize]
+    }
+}
+
+fn ray_attacks(sq: i32, occupied: u64, dir: usize) -> u64 {
+    let ray = RAYS[dir][sq as usize];
+    let blockers = ray & occupied;
+    if blockers == 0 {
+        return ray;
+    }
+
+    // the nearest blocker is the lowest square on rays going up, the highest on rays going down
+    let blocker = if dir < 4 {
+        blockers.trailing_zeros()
+    } else {
+        63 - blockers.leading_zeros()
+    };
+
+    ray ^ RAYS[dir][blocker as usize]
+}
+
+// the number of king moves between two squares
+pub fn distance(a: i32, b: i32) -> i32 {
+    let (a_rank, a_file) = to_rank_file(a);
+    let (b_rank, b_file) = to_rank_file(b);
+    (a_rank - b_rank).abs().max((a_file - b_file).abs())
+}
+
+// the files either side of a file, if they're on the board
+pub fn adjacent_files(file: i32) -> u64 {
+    let mut files = 0;
+    if file > 0 {
+        files |= FILE_A << (file - 1);
+    }
+    if file < 7 {
+        files |= FILE_A << (file + 1);
+    }
+
+    files
+}
+
 pub fn to_rank_file(sq: i32) -> (i32, i32) {
     (sq / 8, sq % 8)
 }
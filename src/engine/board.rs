@@ -1,9 +1,12 @@
+use std::iter;
 use std::sync::Arc;
 use std::u64;
 
-use crate::engine::{bb, gen, hash};
+use crate::engine::{bb, gen, hash, nnue, params};
 use crate::engine::mov::Move;
+use crate::engine::nnue::Accumulator;
 use crate::engine::piece::PieceType;
+use crate::engine::psqt::Psqt;
 use crate::engine::square::Square;
 
 const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
@@ -18,6 +21,9 @@ pub struct Board {
     pub fullmove_number: u16,
     pub previous: Option<Arc<Board>>,
     pub hash: u64,
+    pub pawn_hash: u64,
+    pub psqt: Psqt,
+    pub accumulator: Option<Arc<Accumulator>>,
 }
 
 #[derive(Copy, Clone)]
@@ -47,6 +53,38 @@ pub enum Color {
     BLACK = 0x000000,
 }
 
+impl Placement {
+    pub fn mirror(&self) -> Self {
+
+        // symmetrically swap white/black positions
+        Self {
+            pawns: self.pawns.swap_bytes(),
+            knights: self.knights.swap_bytes(),
+            bishops: self.bishops.swap_bytes(),
+            rooks: self.rooks.swap_bytes(),
+            queens: self.queens.swap_bytes(),
+            kings: self.kings.swap_bytes(),
+
+            // white -> black, black -> white
+            white: self.black.swap_bytes(),
+            black: self.white.swap_bytes(),
+        }
+    }
+}
+
+impl CastleRights {
+    pub fn mirror(&self) -> Self {
+
+        // white rights -> black rights, black rights -> white rights
+        Self {
+            kingside_w: self.kingside_b,
+            queenside_w: self.queenside_b,
+            kingside_b: self.kingside_w,
+            queenside_b: self.queenside_w,
+        }
+    }
+}
+
 impl Color {
     fn other(&self) -> Self {
         match self {
@@ -58,28 +96,49 @@ impl Color {
 
 impl Board {
     pub fn new(fen: &str) -> Board {
+        match Board::parse(fen) {
+            Ok(board) => board,
+            Err(msg) => panic!("{}", msg),
+        }
+    }
+
+    // for fens from files and other input that can't be trusted to be well formed
+    pub fn parse(fen: &str) -> Result<Board, String> {
         let mut parts = fen.split_whitespace();
 
-        let fen_placement = parts.next().expect("expected FEN piece placement");
-        let fen_turn = parts.next().expect("expected FEN active color");
-        let fen_castle_rights = parts.next().expect("expected FEN castling rights");
-        let fen_en_passant_target = parts.next().expect("expected FEN en passant target");
-        let fen_halfmove_clock = parts.next().expect("expected FEN halfmove clock");
-        let fen_fullmove_number = parts.next().expect("expected FEN fullmove number");
+        let fen_placement = parts.next().ok_or("expected FEN piece placement")?;
+        let fen_turn = parts.next().ok_or("expected FEN active color")?;
+        let fen_castle_rights = parts.next().ok_or("expected FEN castling rights")?;
+        let fen_en_passant_target = parts.next().ok_or("expected FEN en passant target")?;
+        let fen_halfmove_clock = parts.next().ok_or("expected FEN halfmove clock")?;
+        let fen_fullmove_number = parts.next().ok_or("expected FEN fullmove number")?;
+
+        // move generation relies on each side having exactly one king
+        let placement = parse_placement(fen_placement).map_err(|_| "failed to parse FEN piece placement")?;
+        let kings = [placement.kings & placement.white, placement.kings & placement.black];
+        if kings.iter().any(|k| k.count_ones() != 1) {
+            return Err(format!("expected one king for each side in FEN: {}", fen));
+        }
 
         let mut board = Self {
-            placement: parse_placement(fen_placement).expect("failed to parse FEN piece placement"),
-            turn: parse_turn(fen_turn).expect("failed to parse FEN en passant target"),
-            castle_rights: parse_castle_rights(fen_castle_rights).expect("failed to parse FEN castling rights"),
+            placement,
+            turn: parse_turn(fen_turn).map_err(|_| "failed to parse FEN active color")?,
+            castle_rights: parse_castle_rights(fen_castle_rights).map_err(|_| "failed to parse FEN castling rights")?,
             en_passant_target: Square::parse(fen_en_passant_target),
-            halfmove_clock: fen_halfmove_clock.parse().expect("failed to parse FEN halfmove clock"),
-            fullmove_number: fen_fullmove_number.parse().expect("failed to parse FEN fullmove number"),
+            halfmove_clock: fen_halfmove_clock.parse().map_err(|_| "failed to parse FEN halfmove clock")?,
+            fullmove_number: fen_fullmove_number.parse().map_err(|_| "failed to parse FEN fullmove number")?,
             previous: None,
             hash: 0,
+            pawn_hash: 0,
+            psqt: Psqt::default(),
+            accumulator: None,
         };
 
         board.hash = hash::of(&board);
-        board
+        board.pawn_hash = hash::pawns_of(&board);
+        board.psqt = params::with(|params| Psqt::of(params, &board.placement));
+        board.accumulator = nnue::with(|network| network.map(|n| Arc::new(Accumulator::of(n, &board.placement))));
+        Ok(board)
     }
 
     pub fn start_pos() -> Board { Board::new(START_FEN) }
@@ -132,9 +191,8 @@ impl Board {
             }
         }
 
-        if *moving != PieceType::PAWN {
-            pawns = bb::clear_bit(pawns, capture_sq);
-        }
+        // always clear pawns, since an en passant capture takes one from beside the destination
+        pawns = bb::clear_bit(pawns, capture_sq);
         if *moving != PieceType::KNIGHT {
             knights = bb::clear_bit(knights, capture_sq);
         }
@@ -244,6 +302,21 @@ impl Board {
             self.fullmove_number + 1
         };
 
+        // update piece-square scores for only the pieces that moved
+        let is_white = self.turn == Color::WHITE;
+        let mut psqt = self.psqt;
+        params::with(|params| {
+            psqt.remove(params, moving, is_white, from_sq);
+            psqt.add(params, setting, is_white, to_sq);
+            if let Some(captured) = self.piece_type_at(capture_sq) {
+                psqt.remove(params, captured, !is_white, capture_sq);
+            }
+            if let Some(rook_move) = castling_rook {
+                psqt.remove(params, &PieceType::ROOK, is_white, rook_move.from.idx as i32);
+                psqt.add(params, &PieceType::ROOK, is_white, rook_move.to.idx as i32);
+            }
+        });
+
         // clone self as a previous position
         let previous = Some(Arc::from(self.clone()));
 
@@ -270,6 +343,36 @@ impl Board {
         self.fullmove_number = fullmove_number;
         self.previous = previous;
         self.hash = hash::of(&self);
+        self.pawn_hash = hash::pawns_of(self);
+        self.psqt = psqt;
+
+        // the network's accumulator follows whatever changed on the board since the last position
+        let previous = self.previous.as_ref().unwrap();
+        let placement = &self.placement;
+        self.accumulator = nnue::with(|network| network.map(|n| Arc::new(match &previous.accumulator {
+            Some(accumulator) => accumulator.update(n, &previous.placement, placement),
+            None => Accumulator::of(n, placement),
+        })));
+    }
+
+    // a null move: the side to move passes, which is never legal but useful to search
+    pub fn pass(&mut self) {
+        let fullmove_number = if self.turn == Color::WHITE {
+            self.fullmove_number
+        } else {
+            self.fullmove_number + 1
+        };
+
+        // clone self as a previous position
+        let previous = Some(Arc::from(self.clone()));
+
+        // nothing before a pass should count as a repetition, so treat it as irreversible
+        self.turn = self.turn.other();
+        self.en_passant_target = None;
+        self.halfmove_clock = 0;
+        self.fullmove_number = fullmove_number;
+        self.previous = previous;
+        self.hash = hash::of(self);
     }
 
     pub fn pop(&mut self) {
@@ -283,6 +386,9 @@ impl Board {
         self.halfmove_clock = previous.halfmove_clock;
         self.fullmove_number = previous.fullmove_number;
         self.hash = previous.hash;
+        self.pawn_hash = previous.pawn_hash;
+        self.psqt = previous.psqt;
+        self.accumulator = previous.accumulator.clone();
         self.previous = previous.previous.clone();
     }
 
@@ -290,25 +396,9 @@ impl Board {
 
         // symmetrically swap white/black positions
         let mut mirror = Board {
-            placement: Placement {
-                pawns: self.placement.pawns.swap_bytes(),
-                knights: self.placement.knights.swap_bytes(),
-                bishops: self.placement.bishops.swap_bytes(),
-                rooks: self.placement.rooks.swap_bytes(),
-                queens: self.placement.queens.swap_bytes(),
-                kings: self.placement.kings.swap_bytes(),
-
-                // white -> black, black -> white
-                white: self.placement.black.swap_bytes(),
-                black: self.placement.white.swap_bytes(),
-            },
+            placement: self.placement.mirror(),
             turn: self.turn.other(),
-            castle_rights: CastleRights {
-                kingside_w: self.castle_rights.kingside_b,
-                queenside_w: self.castle_rights.queenside_b,
-                kingside_b: self.castle_rights.kingside_w,
-                queenside_b: self.castle_rights.queenside_w,
-            },
+            castle_rights: self.castle_rights.mirror(),
             en_passant_target: match self.en_passant_target {
                 Some(square) => Some(square.mirror()),
                 None => None,
@@ -317,9 +407,13 @@ impl Board {
             fullmove_number: self.fullmove_number,
             previous: None,
             hash: 0,
+            pawn_hash: 0,
+            psqt: self.psqt.mirror(),
+            accumulator: self.accumulator.as_ref().map(|a| Arc::new(a.mirror())),
         };
 
         mirror.hash = hash::of(&mirror);
+        mirror.pawn_hash = hash::pawns_of(&mirror);
         mirror
     }
 
@@ -335,6 +429,94 @@ impl Board {
 
         !0 != check_restriction
     }
+
+    // how many plies back each earlier occurrence of this position is, most recent first; nothing
+    // before the last irreversible move can repeat
+    pub fn repetitions(&self) -> impl Iterator<Item = i32> + '_ {
+        iter::successors(self.previous.as_deref(), |board| board.previous.as_deref())
+            .take(self.halfmove_clock as usize)
+            .zip(1..)
+            .filter(move |(board, _)| board.hash == self.hash)
+            .map(|(_, distance)| distance)
+    }
+
+    // neither side can possibly deliver mate: bare kings, a single minor piece, or only bishops
+    // that all stand on the same color
+    pub fn is_insufficient_material(&self) -> bool {
+        let p = &self.placement;
+        if 0 != p.pawns | p.rooks | p.queens {
+            return false;
+        }
+
+        let minors = p.knights | p.bishops;
+        if minors.count_ones() <= 1 {
+            return true;
+        }
+
+        0 == p.knights && (0 == p.bishops & bb::DARK_SQUARES || 0 == p.bishops & !bb::DARK_SQUARES)
+    }
+
+    pub fn piece_type_at(&self, sq: i32) -> Option<&'static PieceType> {
+        if bb::has_bit(self.placement.pawns, sq) {
+            Some(&PieceType::PAWN)
+        } else if bb::has_bit(self.placement.knights, sq) {
+            Some(&PieceType::KNIGHT)
+        } else if bb::has_bit(self.placement.bishops, sq) {
+            Some(&PieceType::BISHOP)
+        } else if bb::has_bit(self.placement.rooks, sq) {
+            Some(&PieceType::ROOK)
+        } else if bb::has_bit(self.placement.queens, sq) {
+            Some(&PieceType::QUEEN)
+        } else if bb::has_bit(self.placement.kings, sq) {
+            Some(&PieceType::KING)
+        } else {
+            None
+        }
+    }
+
+    pub fn fen(&self) -> String {
+        let mut placement = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                let sq = rank * 8 + file;
+                match self.piece_type_at(sq) {
+                    Some(piece_type) => {
+                        if empty > 0 {
+                            placement.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+
+                        let is_white = bb::has_bit(self.placement.white, sq);
+                        placement.push_str(&if is_white { piece_type.symbol.to_uppercase() } else { piece_type.symbol.to_string() });
+                    }
+                    None => empty += 1,
+                }
+            }
+
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                placement.push('/');
+            }
+        }
+
+        let rights = &self.castle_rights;
+        let mut castling: String = [(rights.kingside_w, 'K'), (rights.queenside_w, 'Q'), (rights.kingside_b, 'k'), (rights.queenside_b, 'q')]
+            .iter()
+            .filter(|(right, _)| *right)
+            .map(|(_, c)| *c)
+            .collect();
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        let turn = if self.turn == Color::WHITE { "w" } else { "b" };
+        let en_passant = self.en_passant_target.map_or("-", |sq| sq.symbol);
+
+        format!("{} {} {} {} {} {}", placement, turn, castling, en_passant, self.halfmove_clock, self.fullmove_number)
+    }
 }
 
 fn parse_placement(fen: &str) -> Result<Placement, &str> {
@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Read, Write};
+
+use rand::Rng;
+
+use crate::engine::{bb, gen, hash};
+use crate::engine::board::{Board, Color};
+use crate::engine::mov::{CASTLES, Move};
+use crate::engine::pgn;
+use crate::engine::pgn::PgnGame;
+
+// polyglot entries are 16 bytes: key (8), move (2), weight (2), learn (4); all big-endian
+const ENTRY_SIZE: usize = 16;
+
+#[derive(Copy, Clone)]
+pub struct BookEntry {
+    pub key: u64,
+    pub mov: u16,
+    pub weight: u16,
+}
+
+pub struct Book {
+    entries: Vec<BookEntry>,
+}
+
+pub struct BuildParams {
+    pub max_ply: usize,
+    pub min_games: u32,
+    pub min_elo: u32,
+}
+
+// move statistics from the perspective of the side making the move
+#[derive(Default, Copy, Clone)]
+pub struct MoveStats {
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl MoveStats {
+
+    // the usual polyglot weighting: a win is worth two draws
+    pub fn score(&self) -> u32 {
+        2 * self.wins + self.draws
+    }
+}
+
+pub struct BookBuilder {
+    params: BuildParams,
+    stats: HashMap<(u64, u16), (Move, MoveStats)>,
+    pub games_added: u32,
+    pub games_skipped: u32,
+}
+
+impl Book {
+    pub fn open(path: &str) -> io::Result<Book> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+
+        // anything that isn't a polyglot book is expected to be in the native text format
+        let mut entries = if path.ends_with(".bin") {
+            parse_polyglot(&bytes)?
+        } else {
+            parse_native(&String::from_utf8_lossy(&bytes))?
+        };
+
+        // books are supposed to be sorted already; a stable sort keeps the order within a key
+        entries.sort_by_key(|e| e.key);
+
+        Ok(Book { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn probe(&self, board: &Board) -> Vec<(Move, u16)> {
+        let key = hash::polyglot(board);
+        let start = self.entries.partition_point(|e| e.key < key);
+
+        let legal_moves = gen::gen_moves(board);
+
+        let mut moves = Vec::new();
+        for entry in self.entries[start..].iter().take_while(|e| e.key == key) {
+
+            // ignore anything that isn't legal here, e.g. from a key collision
+            let mov = decode_move(board, entry.mov);
+            if legal_moves.contains(&mov) {
+                moves.push((mov, entry.weight));
+            }
+        }
+
+        moves
+    }
+
+    pub fn choose_move(&self, board: &Board, best: bool) -> Option<Move> {
+        let moves = self.probe(board);
+        if moves.is_empty() {
+            return None;
+        }
+
+        if best {
+            return moves.iter().max_by_key(|m| m.1).map(|m| m.0);
+        }
+
+        // weighted random choice; all-zero weights fall back to a uniform choice
+        let total: u32 = moves.iter().map(|m| m.1 as u32).sum();
+        let mut rng = rand::thread_rng();
+        if total == 0 {
+            return Some(moves[rng.gen_range(0, moves.len())].0);
+        }
+
+        let mut pick = rng.gen_range(0, total);
+        for (mov, weight) in moves.iter() {
+            if pick < *weight as u32 {
+                return Some(*mov);
+            }
+            pick -= *weight as u32;
+        }
+
+        None
+    }
+}
+
+impl BookBuilder {
+    pub fn new(params: BuildParams) -> Self {
+        Self {
+            params,
+            stats: HashMap::new(),
+            games_added: 0,
+            games_skipped: 0,
+        }
+    }
+
+    pub fn add_game(&mut self, game: &PgnGame) {
+        let white_score = game.white_score();
+        if white_score.is_none() || !self.is_rated_enough(game) {
+            self.games_skipped += 1;
+            return;
+        }
+        let white_score = white_score.unwrap();
+
+        let mut board = match game.start_position() {
+            Some(board) => board,
+            None => {
+                self.games_skipped += 1;
+                return;
+            }
+        };
+        for san in game.moves.iter().take(self.params.max_ply) {
+
+            // stop at the first move that can't be understood; the rest of the game is lost
+            let mov = match pgn::parse_san(&board, san) {
+                Some(mov) => mov,
+                None => break,
+            };
+
+            let score = match board.turn {
+                Color::WHITE => white_score,
+                Color::BLACK => 1.0 - white_score,
+            };
+
+            let key = (hash::polyglot(&board), encode_move(&board, &mov));
+            let (_, stats) = self.stats.entry(key).or_insert((mov, MoveStats::default()));
+            stats.games += 1;
+            if score > 0.75 {
+                stats.wins += 1;
+            } else if score > 0.25 {
+                stats.draws += 1;
+            } else {
+                stats.losses += 1;
+            }
+
+            board.push(mov);
+        }
+
+        self.games_added += 1;
+    }
+
+    fn is_rated_enough(&self, game: &PgnGame) -> bool {
+        if self.params.min_elo == 0 {
+            return true;
+        }
+
+        let elo = |header| game.header(header).and_then(|e| e.parse::<u32>().ok()).unwrap_or(0);
+        elo("WhiteElo") >= self.params.min_elo && elo("BlackElo") >= self.params.min_elo
+    }
+
+    fn filtered(&self) -> Vec<(u64, u16, Move, MoveStats)> {
+        let mut entries: Vec<(u64, u16, Move, MoveStats)> = self.stats.iter()
+            .filter(|(_, (_, stats))| stats.games >= self.params.min_games && stats.score() > 0)
+            .map(|((key, raw), (mov, stats))| (*key, *raw, *mov, *stats))
+            .collect();
+
+        // sorted by key, best moves first
+        entries.sort_by(|a, b| a.0.cmp(&b.0).then(b.3.score().cmp(&a.3.score())));
+        entries
+    }
+
+    pub fn write_polyglot(&self, path: &str) -> io::Result<usize> {
+        let entries = self.filtered();
+
+        // scale weights down to fit into 16 bits if necessary
+        let max_score = entries.iter().map(|e| e.3.score()).max().unwrap_or(0) as u64;
+        let scale = |score: u32| {
+            if max_score > u16::MAX as u64 {
+                (score as u64 * u16::MAX as u64 / max_score).max(1) as u16
+            } else {
+                score as u16
+            }
+        };
+
+        let mut out = BufWriter::new(File::create(path)?);
+        for (key, raw, _, stats) in entries.iter() {
+            out.write_all(&key.to_be_bytes())?;
+            out.write_all(&raw.to_be_bytes())?;
+            out.write_all(&scale(stats.score()).to_be_bytes())?;
+            out.write_all(&0u32.to_be_bytes())?;
+        }
+        out.flush()?;
+
+        Ok(entries.len())
+    }
+
+    pub fn write_native(&self, path: &str) -> io::Result<usize> {
+        let entries = self.filtered();
+
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "# key move games wins draws losses")?;
+        for (key, _, mov, stats) in entries.iter() {
+            writeln!(out, "{:016x} {} {} {} {} {}", key, mov.uci(), stats.games, stats.wins, stats.draws, stats.losses)?;
+        }
+        out.flush()?;
+
+        Ok(entries.len())
+    }
+}
+
+pub fn encode_move(board: &Board, mov: &Move) -> u16 {
+    let mut to = mov.to;
+
+    // castling is encoded as the king capturing its own rook
+    if bb::has_bit(board.placement.kings, mov.from.idx as i32) {
+        for castle in CASTLES.iter() {
+            let king = castle.0;
+            let rook = castle.1;
+            if king.0.idx == mov.from.idx && king.1.idx == mov.to.idx {
+                to = rook.0;
+            }
+        }
+    }
+
+    Move {
+        from: mov.from,
+        to,
+        promotion: mov.promotion,
+    }.pack()
+}
+
+pub fn decode_move(board: &Board, raw: u16) -> Move {
+    let mov = Move::unpack(raw);
+    let from = mov.from;
+    let mut to = mov.to;
+
+    // castling is encoded as the king capturing its own rook
+    if bb::has_bit(board.placement.kings, from.idx as i32) {
+        for castle in CASTLES.iter() {
+            let king = castle.0;
+            let rook = castle.1;
+            if king.0.idx == from.idx && rook.0.idx == to.idx {
+                to = king.1;
+            }
+        }
+    }
+
+    Move {
+        from,
+        to,
+        promotion: mov.promotion,
+    }
+}
+
+fn parse_polyglot(bytes: &[u8]) -> io::Result<Vec<BookEntry>> {
+    if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated polyglot book"));
+    }
+
+    Ok(bytes.chunks(ENTRY_SIZE).map(parse_entry).collect())
+}
+
+fn parse_native(text: &str) -> io::Result<Vec<BookEntry>> {
+    let invalid = |line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("bad book line: {}", line));
+
+    let mut entries = Vec::new();
+    for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() != 6 {
+            return Err(invalid(line));
+        }
+
+        let key = u64::from_str_radix(parts[0], 16).map_err(|_| invalid(line))?;
+        let mov = Move::parse(parts[1]).ok_or_else(|| invalid(line))?;
+        let count = |part: &str| part.parse::<u32>().map_err(|_| invalid(line));
+        let stats = MoveStats {
+            games: count(parts[2])?,
+            wins: count(parts[3])?,
+            draws: count(parts[4])?,
+            losses: count(parts[5])?,
+        };
+
+        entries.push(BookEntry {
+            key,
+            mov: mov.pack(),
+            weight: stats.score().min(u16::MAX as u32) as u16,
+        });
+    }
+
+    Ok(entries)
+}
+
+fn parse_entry(bytes: &[u8]) -> BookEntry {
+    BookEntry {
+        key: read_be(&bytes[0..8]),
+        mov: read_be(&bytes[8..10]) as u16,
+        weight: read_be(&bytes[10..12]) as u16,
+    }
+}
+
+fn read_be(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |value, byte| (value << 8) | *byte as u64)
+}
@@ -0,0 +1,153 @@
+use crate::engine::{bb, eval};
+use crate::engine::board::{Board, Placement};
+use crate::engine::params::EvalParams;
+use crate::engine::piece::PieceType;
+
+// endgame scores are multiplied by a scale factor out of this; zero is a draw
+pub const SCALE_NORMAL: i32 = 64;
+
+// the pieces one side has, besides its king
+struct Material {
+    pawns: u32,
+    knights: u32,
+    bishops: u32,
+    rooks: u32,
+    queens: u32,
+
+    // how many square colors the bishops stand on, since bishops on one color can't mate
+    bishop_colors: u32,
+}
+
+impl Material {
+    fn of(p: &Placement, own: u64) -> Self {
+        let bishops = p.bishops & own;
+        Self {
+            pawns: (p.pawns & own).count_ones(),
+            knights: (p.knights & own).count_ones(),
+            bishops: bishops.count_ones(),
+            rooks: (p.rooks & own).count_ones(),
+            queens: (p.queens & own).count_ones(),
+            bishop_colors: (0 != bishops & bb::DARK_SQUARES) as u32 + (0 != bishops & !bb::DARK_SQUARES) as u32,
+        }
+    }
+
+    fn is_bare(&self) -> bool {
+        self.pawns + self.knights + self.bishops + self.rooks + self.queens == 0
+    }
+
+    fn is_only(&self, knights: u32, bishops: u32, rooks: u32) -> bool {
+        self.pawns == 0 && self.knights == knights && self.bishops == bishops && self.rooks == rooks && self.queens == 0
+    }
+}
+
+// an evaluation from white's perspective for endings that need knowing how to win, if this is one
+pub fn evaluate(params: &EvalParams, board: &Board) -> Option<i32> {
+    let p = &board.placement;
+    let white = Material::of(p, p.white);
+    let black = Material::of(p, p.black);
+
+    if black.is_bare() && can_force_mate(&white) {
+        Some(evaluate_mate(params, p, &white))
+    } else if white.is_bare() && can_force_mate(&black) {
+        Some(-evaluate_mate(params, &p.mirror(), &black))
+    } else {
+        None
+    }
+}
+
+// how much of the endgame score to keep, out of SCALE_NORMAL, in endings that are hard to win
+pub fn scale_factor(params: &EvalParams, board: &Board, eg: i32) -> i32 {
+    let p = &board.placement;
+    let white = Material::of(p, p.white);
+    let black = Material::of(p, p.black);
+
+    // the stronger side as white
+    let (p, strong, weak) = if eg >= 0 {
+        (*p, white, black)
+    } else {
+        (p.mirror(), black, white)
+    };
+
+    // bishops on opposite colors can't contest each other's squares, so pawns are easily blocked
+    let is_bishop_only = |m: &Material| m.knights == 0 && m.bishops == 1 && m.rooks == 0 && m.queens == 0;
+    if is_bishop_only(&strong) && is_bishop_only(&weak) {
+        let on_dark = p.bishops & bb::DARK_SQUARES;
+        if on_dark != 0 && on_dark != p.bishops {
+            return params.scale_opposite_bishops;
+        }
+    }
+
+    // a rook against a bishop is rarely won
+    if strong.is_only(0, 0, 1) && weak.is_only(0, 1, 0) {
+        return params.scale_rook_bishop;
+    }
+
+    // a bishop that doesn't control the promotion square of a rook pawn can't drive out the king
+    if is_bishop_only(&strong) && weak.is_bare() && is_wrong_bishop(&p) {
+        return 0;
+    }
+
+    SCALE_NORMAL
+}
+
+// only a bishop and rook pawns on one edge, and the defending king is already in the corner
+fn is_wrong_bishop(p: &Placement) -> bool {
+    let pawns = p.pawns & p.white;
+    let promotion = if pawns != 0 && 0 == pawns & !bb::FILE_A {
+        56
+    } else if pawns != 0 && 0 == pawns & !bb::FILE_H {
+        63
+    } else {
+        return false;
+    };
+
+    let is_bishop_dark = 0 != p.bishops & p.white & bb::DARK_SQUARES;
+    let is_promotion_dark = bb::has_bit(bb::DARK_SQUARES, promotion);
+    let their_king = bb::to_sq(p.kings & p.black);
+
+    is_bishop_dark != is_promotion_dark && bb::distance(their_king, promotion) <= 1
+}
+
+fn can_force_mate(m: &Material) -> bool {
+    m.queens > 0 || m.rooks > 0 || (m.bishops > 0 && m.knights > 0) || m.bishop_colors > 1
+}
+
+// the lone king is black; drive it to the edge, or to a corner the bishop can cover
+fn evaluate_mate(params: &EvalParams, p: &Placement, strong: &Material) -> i32 {
+    let king = bb::to_sq(p.kings & p.white);
+    let their_king = bb::to_sq(p.kings & p.black);
+
+    // a won ending is worth more than any material, but less than a mate found by search
+    let mut eval = params.known_win + material(params, strong);
+    // drive the lone king where it can be mated, and bring the kings together
+    eval += params.push_close * (7 - bb::distance(king, their_king));
+
+    let is_bishop_and_knight = strong.queens == 0 && strong.rooks == 0 && strong.bishops == 1;
+    if is_bishop_and_knight {
+
+        // only the corners of the bishop's color will do
+        let corners = if 0 != p.bishops & bb::DARK_SQUARES { [0, 63] } else { [7, 56] };
+        let corner_distance = corners.iter().map(|c| bb::distance(their_king, *c)).min().unwrap();
+        eval += params.push_to_corner * (7 - corner_distance);
+    } else {
+        eval += params.push_to_edge * (6 - edge_distance(their_king));
+    }
+
+    eval
+}
+
+// the king moves to the nearest edge, along both ranks and files
+fn edge_distance(sq: i32) -> i32 {
+    let (rank, file) = bb::to_rank_file(sq);
+    rank.min(7 - rank) + file.min(7 - file)
+}
+
+fn material(params: &EvalParams, m: &Material) -> i32 {
+    let mut material = 0;
+    material += eval::piece_value(params, &PieceType::KNIGHT) * m.knights as i32;
+    material += eval::piece_value(params, &PieceType::BISHOP) * m.bishops as i32;
+    material += eval::piece_value(params, &PieceType::ROOK) * m.rooks as i32;
+    material += eval::piece_value(params, &PieceType::QUEEN) * m.queens as i32;
+
+    material
+}
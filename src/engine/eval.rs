@@ -1,83 +1,90 @@
-use crate::engine::board::{Board, Color};
-use crate::engine::mov::Move;
+use crate::engine::{endgame, EvalTerm, EvalTrace, king, mobility, nnue, params, pawns, psqt};
+use crate::engine::board::Board;
+use crate::engine::params::EvalParams;
 use crate::engine::piece::PieceType;
 
-const PAWN_VALUE: i32 = 100;
-const KNIGHT_VALUE: i32 = 300;
-const BISHOP_VALUE: i32 = 300;
-const ROOK_VALUE: i32 = 500;
-const QUEEN_VALUE: i32 = 900;
-
+// from white's perspective; material and placement are kept up to date by the board itself
 pub fn evaluate(board: &Board) -> i32 {
-    let white_pawns = board.placement.white & board.placement.pawns;
-    let white_knights = board.placement.white & board.placement.knights;
-    let white_bishops = board.placement.white & board.placement.bishops;
-    let white_rooks = board.placement.white & board.placement.rooks;
-    let white_queens = board.placement.white & board.placement.queens;
-
-    let black_pawns = board.placement.black & board.placement.pawns;
-    let black_knights = board.placement.black & board.placement.knights;
-    let black_bishops = board.placement.black & board.placement.bishops;
-    let black_rooks = board.placement.black & board.placement.rooks;
-    let black_queens = board.placement.black & board.placement.queens;
-
-    let mut eval = 0;
-    eval += PAWN_VALUE * white_pawns.count_ones() as i32;
-    eval += KNIGHT_VALUE * white_knights.count_ones() as i32;
-    eval += BISHOP_VALUE * white_bishops.count_ones() as i32;
-    eval += ROOK_VALUE * white_rooks.count_ones() as i32;
-    eval += QUEEN_VALUE * white_queens.count_ones() as i32;
-
-    eval -= PAWN_VALUE * black_pawns.count_ones() as i32;
-    eval -= KNIGHT_VALUE * black_knights.count_ones() as i32;
-    eval -= BISHOP_VALUE * black_bishops.count_ones() as i32;
-    eval -= ROOK_VALUE * black_rooks.count_ones() as i32;
-    eval -= QUEEN_VALUE * black_queens.count_ones() as i32;
 
-    eval
+    // a network, when one is switched on, replaces every handcrafted term
+    match nnue::evaluate(board) {
+        Some(_) if board.is_insufficient_material() => 0,
+        Some(eval) => eval,
+        None => params::with(|params| evaluate_with(params, board)),
+    }
 }
 
-pub fn evaluate_exchange(board: &Board, mov: &Move) -> i32 {
-
-    let mut capture_sq = mov.to.idx as i32;
-    if let Some(ep_target) = board.en_passant_target {
-        let moving_pawn = 0 != board.placement.pawns & (1 << mov.from.idx);
-        if moving_pawn && ep_target.idx == mov.to.idx {
-            match board.turn {
-                Color::WHITE => capture_sq -= 8,
-                Color::BLACK => capture_sq += 8,
-            }
-        }
+fn evaluate_with(params: &EvalParams, board: &Board) -> i32 {
+    if board.is_insufficient_material() {
+        return 0;
     }
 
-    let mut promotion_eval = 0;
-    if let Some(piece_type) = mov.promotion {
-        promotion_eval += evaluate_piece(piece_type, mov.to.idx as i32);
+    // some endings are won by technique rather than by the usual terms
+    if let Some(eval) = endgame::evaluate(params, board) {
+        return eval;
     }
 
-    evaluate_sq(board, capture_sq) - evaluate_sq(board, mov.from.idx as i32) + promotion_eval
+    let psqt = &board.psqt;
+    let (pawns_mg, pawns_eg) = pawns::evaluate(params, board);
+    let king_mg = king::evaluate(params, board);
+    let (mobility_mg, mobility_eg) = mobility::evaluate(params, board);
+
+    let mg = psqt.mg + pawns_mg + king_mg + mobility_mg;
+    let eg = psqt.eg + pawns_eg + mobility_eg;
+    let eg = eg * endgame::scale_factor(params, board, eg) / endgame::SCALE_NORMAL;
+
+    psqt.taper(mg, eg)
+}
+
+pub fn trace(board: &Board) -> EvalTrace {
+    params::with(|params| trace_with(params, board))
 }
 
-fn evaluate_sq(board: &Board, sq: i32) -> i32 {
-    let bit = 1 << sq;
+fn trace_with(params: &EvalParams, board: &Board) -> EvalTrace {
+    let (white_material, white_squares) = psqt::trace(params, &board.placement, true);
+    let (black_material, black_squares) = psqt::trace(params, &board.placement, false);
+    let (white_pawns, black_pawns) = pawns::trace(params, board);
+    let (white_king, black_king) = king::trace(params, board);
+    let (white_mobility, black_mobility) = mobility::trace(params, board);
 
-    let mut eval = 0;
-    eval += PAWN_VALUE * ((bit & board.placement.pawns) >> sq) as i32;
-    eval += KNIGHT_VALUE * ((bit & board.placement.knights) >> sq) as i32;
-    eval += BISHOP_VALUE * ((bit & board.placement.bishops) >> sq) as i32;
-    eval += ROOK_VALUE * ((bit & board.placement.rooks) >> sq) as i32;
-    eval += QUEEN_VALUE * ((bit & board.placement.queens) >> sq) as i32;
+    let terms = vec![
+        EvalTerm { name: "material", white: white_material, black: black_material },
+        EvalTerm { name: "placement", white: white_squares, black: black_squares },
+        EvalTerm { name: "pawns", white: white_pawns, black: black_pawns },
+        EvalTerm { name: "king safety", white: (white_king, 0), black: (black_king, 0) },
+        EvalTerm { name: "mobility", white: white_mobility, black: black_mobility },
+    ];
 
-    eval
+    let special = if board.is_insufficient_material() {
+        Some("insufficient material")
+    } else if endgame::evaluate(params, board).is_some() {
+        Some("known win")
+    } else {
+        None
+    };
+
+    let eg: i32 = terms.iter().map(|t| t.white.1 - t.black.1).sum();
+    EvalTrace {
+        terms,
+        phase: board.psqt.phase.min(psqt::MAX_PHASE),
+        max_phase: psqt::MAX_PHASE,
+        scale: endgame::scale_factor(params, board, eg),
+        max_scale: endgame::SCALE_NORMAL,
+        special,
+
+        // the terms are shown even when a network decides the eval instead
+        network: nnue::evaluate(board).is_some(),
+        eval: evaluate(board),
+    }
 }
 
-fn evaluate_piece(piece_type: &PieceType, _sq: i32) -> i32 {
+pub fn piece_value(params: &EvalParams, piece_type: &PieceType) -> i32 {
     match piece_type {
-        &PieceType::PAWN => PAWN_VALUE,
-        &PieceType::KNIGHT => KNIGHT_VALUE,
-        &PieceType::BISHOP => BISHOP_VALUE,
-        &PieceType::ROOK => ROOK_VALUE,
-        &PieceType::QUEEN => QUEEN_VALUE,
+        &PieceType::PAWN => params.piece_values[0],
+        &PieceType::KNIGHT => params.piece_values[1],
+        &PieceType::BISHOP => params.piece_values[2],
+        &PieceType::ROOK => params.piece_values[3],
+        &PieceType::QUEEN => params.piece_values[4],
         _ => 0,
     }
 }
@@ -1,30 +1,160 @@
 use crate::engine::bb;
 use crate::engine::bb::BitIterator;
-use crate::engine::board::{Board, Color, Placement};
+use crate::engine::board::{Board, CastleRights, Color, Placement};
 use crate::engine::mov::{KINGSIDE_CASTLE_W, Move, QUEENSIDE_CASTLE_W};
+use crate::engine::piece::PieceType;
 use crate::engine::square::Square;
 
+const RANK_8: u64 = 0xff00000000000000;
+
 pub fn gen_moves(board: &Board) -> Vec<Move> {
+    gen_masked(board, |_| (!0, !0))
+}
 
-    // always generate moves from the perspective of the white pieces
-    let mirror;
-    let position = if board.turn == Color::BLACK {
-        mirror = board.mirror();
-        &mirror
+// captures, including en passant, and every promotion
+pub fn gen_captures(board: &Board) -> Vec<Move> {
+    gen_masked(board, |position| {
+        let ep_capture = position.en_passant_target.map_or(0, |sq| 1 << sq.idx);
+        (position.placement.black, position.placement.black | ep_capture | RANK_8)
+    })
+}
+
+// everything not generated by gen_captures
+pub fn gen_quiets(board: &Board) -> Vec<Move> {
+    gen_masked(board, |position| {
+        let ep_capture = position.en_passant_target.map_or(0, |sq| 1 << sq.idx);
+        let occupied = position.placement.white | position.placement.black;
+        (!occupied, !(occupied | ep_capture | RANK_8))
+    })
+}
+
+// quiet moves that give check, directly or by uncovering a slider
+pub fn gen_quiet_checks(board: &Board) -> Vec<Move> {
+    let mut moves = gen_quiets(board);
+    moves.retain(|m| gives_check(board, m));
+
+    moves
+}
+
+// captures, en passant and promotions are the only moves that aren't quiet
+pub fn is_quiet(board: &Board, mov: &Move) -> bool {
+    let is_pawn = bb::has_bit(board.placement.pawns, mov.from.idx as i32);
+    let ep_capture = board.en_passant_target.filter(|_| is_pawn).map_or(0, |sq| 1 << sq.idx);
+    let occupied = board.placement.white | board.placement.black | ep_capture;
+    mov.promotion.is_none() && !bb::has_bit(occupied, mov.to.idx as i32)
+}
+
+pub fn gives_check(board: &Board, mov: &Move) -> bool {
+    let placement = &board.placement;
+    let white = board.turn == Color::WHITE;
+    let (us, them) = if white {
+        (placement.white, placement.black)
+    } else {
+        (placement.black, placement.white)
+    };
+
+    let from = mov.from.idx as i32;
+    let to = mov.to.idx as i32;
+    let king_sq = bb::to_sq(them & placement.kings);
+
+    // the board after the move; only the moving pieces matter for check
+    let mut occupied = bb::set_bit(bb::clear_bit(us | them, from), to);
+    let ep_capture = board.en_passant_target.filter(|sq| sq.idx == mov.to.idx);
+    if ep_capture.is_some() && bb::has_bit(placement.pawns, from) {
+        occupied = bb::clear_bit(occupied, if white { to - 8 } else { to + 8 });
+    }
+
+    let piece = match mov.promotion {
+        Some(piece_type) => piece_type,
+        None => board.piece_type_at(from).expect("no piece to move"),
+    };
+
+    // a castling king gives check with its rook
+    let mut moved = bb::set_bit(0, from);
+    let (piece, to) = match mov.get_castling_rook() {
+        Some(rook) if *piece == PieceType::KING => {
+            moved = bb::set_bit(moved, rook.from.idx as i32);
+            occupied = bb::set_bit(bb::clear_bit(occupied, rook.from.idx as i32), rook.to.idx as i32);
+            (&PieceType::ROOK, rook.to.idx as i32)
+        }
+        _ => (piece, to),
+    };
+
+    // a pawn attacks the squares from which a pawn of the other color would attack it
+    let direct = match *piece {
+        PieceType::PAWN => bb::pawn_attackers(to, !white),
+        PieceType::KNIGHT => bb::KNIGHT_MOVES[to as usize],
+        PieceType::BISHOP => bb::bishop_attacks(to, occupied),
+        PieceType::ROOK => bb::rook_attacks(to, occupied),
+        PieceType::QUEEN => bb::bishop_attacks(to, occupied) | bb::rook_attacks(to, occupied),
+        _ => 0,
+    };
+    if bb::has_bit(direct, king_sq) {
+        return true;
+    }
+
+    // discovered checks from sliders that didn't move
+    let sliders = us & !moved;
+    let diagonal = sliders & (placement.bishops | placement.queens);
+    let line = sliders & (placement.rooks | placement.queens);
+
+    0 != (bb::bishop_attacks(king_sq, occupied) & diagonal) | (bb::rook_attacks(king_sq, occupied) & line)
+}
+
+// legality of a single move, i.e. one from the transposition table, without generating the rest
+pub fn is_legal(board: &Board, mov: &Move) -> bool {
+    let position = Perspective::new(board);
+    let mov = if board.turn == Color::BLACK { mov.mirror() } else { *mov };
+
+    let sq = mov.from.idx as i32;
+    if !bb::has_bit(position.placement.white, sq) {
+        return false;
+    }
+
+    let check_restriction = position.check_restriction();
+    let placement = &position.placement;
+    let moves = if bb::has_bit(placement.pawns, sq) {
+        gen_pawn_moves_from(&position, sq, check_restriction)
+    } else if bb::has_bit(placement.knights, sq) {
+        gen_knight_moves_from(&position, sq, check_restriction)
+    } else if bb::has_bit(placement.bishops, sq) {
+        gen_bishop_moves_from(&position, sq, check_restriction)
+    } else if bb::has_bit(placement.rooks, sq) {
+        gen_rook_moves_from(&position, sq, check_restriction)
+    } else if bb::has_bit(placement.queens, sq) {
+        gen_queen_moves_from(&position, sq, check_restriction)
     } else {
-        board
+        let mut moves = gen_king_moves_from(&position, sq, !0);
+        moves.append(&mut gen_castling_moves(&position, check_restriction));
+        moves
     };
 
-    let check_restriction = get_check_restriction(position);
+    moves.contains(&mov)
+}
+
+// the masks are built from the white perspective and limit the destination squares, of pieces
+// and then of pawns, since only pawns capture en passant or promote
+fn gen_masked(board: &Board, mask_fn: fn(&Perspective) -> (u64, u64)) -> Vec<Move> {
+
+    // always generate moves from the perspective of the white pieces
+    let position = &Perspective::new(board);
+
+    let (mask, pawn_mask) = mask_fn(position);
+    let check_restriction = position.check_restriction();
+    let restriction = check_restriction & mask;
 
     let mut moves = Vec::new();
-    moves.append(&mut gen_pawn_moves(position, check_restriction));
-    moves.append(&mut gen_knight_moves(position, check_restriction));
-    moves.append(&mut gen_bishop_moves(position, check_restriction));
-    moves.append(&mut gen_rook_moves(position, check_restriction));
-    moves.append(&mut gen_queen_moves(position, check_restriction));
-    moves.append(&mut gen_king_moves(position));
-    moves.append(&mut gen_castling_moves(position, check_restriction));
+    moves.append(&mut gen_pawn_moves(position, check_restriction & pawn_mask));
+    moves.append(&mut gen_knight_moves(position, restriction));
+    moves.append(&mut gen_bishop_moves(position, restriction));
+    moves.append(&mut gen_rook_moves(position, restriction));
+    moves.append(&mut gen_queen_moves(position, restriction));
+    moves.append(&mut gen_king_moves(position, mask));
+    for mov in gen_castling_moves(position, check_restriction) {
+        if bb::has_bit(mask, mov.to.idx as i32) {
+            moves.push(mov);
+        }
+    }
 
     // mirror the moves back to black perspective if necessary
     if board.turn == Color::BLACK {
@@ -36,6 +166,37 @@ pub fn gen_moves(board: &Board) -> Vec<Move> {
     moves
 }
 
+// the parts of the board that move generation reads, with the side to move as white; mirroring
+// only these is much cheaper than mirroring the board, which also recomputes its hashes
+struct Perspective {
+    placement: Placement,
+    castle_rights: CastleRights,
+    en_passant_target: Option<&'static Square>,
+}
+
+impl Perspective {
+    fn new(board: &Board) -> Self {
+        if board.turn == Color::BLACK {
+            Self {
+                placement: board.placement.mirror(),
+                castle_rights: board.castle_rights.mirror(),
+                en_passant_target: board.en_passant_target.map(|sq| sq.mirror()),
+            }
+        } else {
+            Self {
+                placement: board.placement,
+                castle_rights: board.castle_rights,
+                en_passant_target: board.en_passant_target,
+            }
+        }
+    }
+
+    fn check_restriction(&self) -> u64 {
+        let king = self.placement.white & self.placement.kings;
+        get_check_restriction_at(&self.placement, bb::to_sq(king))
+    }
+}
+
 pub fn get_check_restriction(board: &Board) -> u64 {
     let king = board.placement.white & board.placement.kings;
     let king_sq = bb::to_sq(king);
@@ -89,7 +250,7 @@ fn get_check_restriction_at(placement: &Placement, king_sq: i32) -> u64 {
     check_restriction
 }
 
-fn get_pin_restriction(board: &Board, sq: i32) -> u64 {
+fn get_pin_restriction(board: &Perspective, sq: i32) -> u64 {
 
     // piece placements after the square is cleared
     let mut into_placement = board.placement;
@@ -108,7 +269,7 @@ fn get_pin_restriction(board: &Board, sq: i32) -> u64 {
     get_check_restriction_at(&into_placement, bb::to_sq(king))
 }
 
-fn gen_pawn_moves(board: &Board, check_restriction: u64) -> Vec<Move> {
+fn gen_pawn_moves(board: &Perspective, check_restriction: u64) -> Vec<Move> {
     let mut moves = Vec::new();
 
     let pawns = board.placement.white & board.placement.pawns;
@@ -119,7 +280,7 @@ fn gen_pawn_moves(board: &Board, check_restriction: u64) -> Vec<Move> {
     moves
 }
 
-fn gen_pawn_moves_from(board: &Board, sq: i32, check_restriction: u64) -> Vec<Move> {
+fn gen_pawn_moves_from(board: &Perspective, sq: i32, check_restriction: u64) -> Vec<Move> {
     let mut moves = Vec::new();
     let from = Square::SQUARES[sq as usize];
 
@@ -162,6 +323,11 @@ fn gen_pawn_moves_from(board: &Board, sq: i32, check_restriction: u64) -> Vec<Mo
             continue;
         }
 
+        // en passant removes two pawns from the same rank, which can uncover the king
+        if 0 != ep_capture & (1 << to_sq) && is_en_passant_into_check(board, sq, to_sq) {
+            continue;
+        }
+
         let mov = Move {
             from,
             to: Square::SQUARES[to_sq as usize],
@@ -179,7 +345,7 @@ fn gen_pawn_moves_from(board: &Board, sq: i32, check_restriction: u64) -> Vec<Mo
     moves
 }
 
-fn gen_knight_moves(board: &Board, check_restriction: u64) -> Vec<Move> {
+fn gen_knight_moves(board: &Perspective, check_restriction: u64) -> Vec<Move> {
     let mut moves = Vec::new();
 
     let knights = board.placement.white & board.placement.knights;
@@ -190,7 +356,7 @@ fn gen_knight_moves(board: &Board, check_restriction: u64) -> Vec<Move> {
     moves
 }
 
-fn gen_knight_moves_from(board: &Board, sq: i32, check_restriction: u64) -> Vec<Move> {
+fn gen_knight_moves_from(board: &Perspective, sq: i32, check_restriction: u64) -> Vec<Move> {
     let mut moves = Vec::new();
     let from = Square::SQUARES[sq as usize];
 
@@ -209,7 +375,7 @@ fn gen_knight_moves_from(board: &Board, sq: i32, check_restriction: u64) -> Vec<
     moves
 }
 
-fn gen_bishop_moves(board: &Board, check_restriction: u64) -> Vec<Move> {
+fn gen_bishop_moves(board: &Perspective, check_restriction: u64) -> Vec<Move> {
     let mut moves = Vec::new();
 
     let bishops = board.placement.white & board.placement.bishops;
@@ -220,7 +386,7 @@ fn gen_bishop_moves(board: &Board, check_restriction: u64) -> Vec<Move> {
     moves
 }
 
-fn gen_bishop_moves_from(board: &Board, sq: i32, check_restriction: u64) -> Vec<Move> {
+fn gen_bishop_moves_from(board: &Perspective, sq: i32, check_restriction: u64) -> Vec<Move> {
     let mut moves = Vec::new();
     let from = Square::SQUARES[sq as usize];
 
@@ -245,7 +411,7 @@ fn gen_bishop_moves_from(board: &Board, sq: i32, check_restriction: u64) -> Vec<
     moves
 }
 
-fn gen_rook_moves(board: &Board, check_restriction: u64) -> Vec<Move> {
+fn gen_rook_moves(board: &Perspective, check_restriction: u64) -> Vec<Move> {
     let mut moves = Vec::new();
 
     let rooks = board.placement.white & board.placement.rooks;
@@ -256,7 +422,7 @@ fn gen_rook_moves(board: &Board, check_restriction: u64) -> Vec<Move> {
     moves
 }
 
-fn gen_rook_moves_from(board: &Board, sq: i32, check_restriction: u64) -> Vec<Move> {
+fn gen_rook_moves_from(board: &Perspective, sq: i32, check_restriction: u64) -> Vec<Move> {
     let mut moves = Vec::new();
     let from = Square::SQUARES[sq as usize];
 
@@ -281,7 +447,7 @@ fn gen_rook_moves_from(board: &Board, sq: i32, check_restriction: u64) -> Vec<Mo
     moves
 }
 
-fn gen_queen_moves(board: &Board, check_restriction: u64) -> Vec<Move> {
+fn gen_queen_moves(board: &Perspective, check_restriction: u64) -> Vec<Move> {
     let mut moves = Vec::new();
 
     let queens = board.placement.white & board.placement.queens;
@@ -292,7 +458,7 @@ fn gen_queen_moves(board: &Board, check_restriction: u64) -> Vec<Move> {
     moves
 }
 
-fn gen_queen_moves_from(board: &Board, sq: i32, check_restriction: u64) -> Vec<Move> {
+fn gen_queen_moves_from(board: &Perspective, sq: i32, check_restriction: u64) -> Vec<Move> {
     let mut moves = Vec::new();
     let from = Square::SQUARES[sq as usize];
 
@@ -317,22 +483,22 @@ fn gen_queen_moves_from(board: &Board, sq: i32, check_restriction: u64) -> Vec<M
     moves
 }
 
-fn gen_king_moves(board: &Board) -> Vec<Move> {
+fn gen_king_moves(board: &Perspective, mask: u64) -> Vec<Move> {
     let mut moves = Vec::new();
 
     let kings = board.placement.white & board.placement.kings;
     for sq in BitIterator::from(kings) {
-        moves.append(&mut gen_king_moves_from(board, sq));
+        moves.append(&mut gen_king_moves_from(board, sq, mask));
     }
 
     moves
 }
 
-fn gen_king_moves_from(board: &Board, sq: i32) -> Vec<Move> {
+fn gen_king_moves_from(board: &Perspective, sq: i32, mask: u64) -> Vec<Move> {
     let mut moves = Vec::new();
     let from = Square::SQUARES[sq as usize];
 
-    let targets = bb::KING_MOVES[sq as usize];
+    let targets = bb::KING_MOVES[sq as usize] & mask;
     for to_sq in BitIterator::from(targets) {
         let blockers = board.placement.white;
         let captures = board.placement.black;
@@ -355,7 +521,7 @@ fn gen_king_moves_from(board: &Board, sq: i32) -> Vec<Move> {
     moves
 }
 
-fn gen_castling_moves(board: &Board, check_restriction: u64) -> Vec<Move> {
+fn gen_castling_moves(board: &Perspective, check_restriction: u64) -> Vec<Move> {
     let mut moves = Vec::new();
 
     // cannot castle while in check
@@ -421,7 +587,7 @@ fn gen_castling_moves(board: &Board, check_restriction: u64) -> Vec<Move> {
     moves
 }
 
-fn is_into_check(board: &Board, king_sq: i32, to_sq: i32) -> bool {
+fn is_into_check(board: &Perspective, king_sq: i32, to_sq: i32) -> bool {
 
     // can't approach opposing king
     let opposition = bb::KING_MOVES[to_sq as usize] & board.placement.black & board.placement.kings;
@@ -450,3 +616,16 @@ fn is_into_check(board: &Board, king_sq: i32, to_sq: i32) -> bool {
 
     0 != !get_check_restriction_at(&into_placement, to_sq)
 }
+
+fn is_en_passant_into_check(board: &Perspective, from_sq: i32, to_sq: i32) -> bool {
+    let captured_sq = to_sq - 8;
+
+    // piece placements after the capture
+    let mut into_placement = board.placement;
+    into_placement.pawns = bb::set_bit(bb::clear_bit(bb::clear_bit(into_placement.pawns, from_sq), captured_sq), to_sq);
+    into_placement.white = bb::set_bit(bb::clear_bit(into_placement.white, from_sq), to_sq);
+    into_placement.black = bb::clear_bit(into_placement.black, captured_sq);
+
+    let king = into_placement.white & into_placement.kings;
+    0 != !get_check_restriction_at(&into_placement, bb::to_sq(king))
+}
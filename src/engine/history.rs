@@ -0,0 +1,99 @@
+use crate::engine::board::{Board, Color};
+use crate::engine::mov::Move;
+use crate::engine::piece::PieceType;
+
+const MAX_PLY: usize = 256;
+const MAX_HISTORY: i32 = 1 << 14;
+
+// one piece on one square; continuation history pairs the last move with the current one
+const PIECE_SQUARES: usize = 6 * 64;
+
+// quiet move ordering heuristics, learned from beta cutoffs and kept separately by each thread
+pub struct History {
+    killers: Vec<[Option<Move>; 2]>,
+    butterfly: Vec<i32>,
+    countermoves: Vec<Option<Move>>,
+    continuation: Vec<i32>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            killers: vec![[None; 2]; MAX_PLY],
+            butterfly: vec![0; 2 * 64 * 64],
+            countermoves: vec![None; 64 * 64],
+            continuation: vec![0; PIECE_SQUARES * PIECE_SQUARES],
+        }
+    }
+
+    pub fn killers(&self, ply: i32) -> [Option<Move>; 2] {
+        self.killers.get(ply as usize).copied().unwrap_or([None; 2])
+    }
+
+    pub fn countermove(&self, previous: Option<Move>) -> Option<Move> {
+        previous.and_then(|p| self.countermoves[move_index(&p)])
+    }
+
+    pub fn score(&self, position: &Board, previous: Option<Move>, mov: &Move) -> i32 {
+        let mut score = self.butterfly[butterfly_index(position, mov)];
+        if let Some(i) = continuation_index(position, previous, mov) {
+            score += self.continuation[i];
+        }
+
+        score
+    }
+
+    // reward the quiet move that caused a cutoff and penalize the quiet moves tried before it
+    pub fn update(&mut self, position: &Board, ply: i32, previous: Option<Move>, best: &Move, tried: &[Move], depth: i32) {
+        if let Some(killers) = self.killers.get_mut(ply as usize) {
+            if killers[0] != Some(*best) {
+                killers[1] = killers[0];
+                killers[0] = Some(*best);
+            }
+        }
+
+        if let Some(p) = previous {
+            self.countermoves[move_index(&p)] = Some(*best);
+        }
+
+        let bonus = (depth * depth).min(MAX_HISTORY / 4);
+        for mov in tried {
+            let bonus = if mov == best { bonus } else { -bonus };
+
+            let i = butterfly_index(position, mov);
+            apply_bonus(&mut self.butterfly[i], bonus);
+
+            if let Some(i) = continuation_index(position, previous, mov) {
+                apply_bonus(&mut self.continuation[i], bonus);
+            }
+        }
+    }
+}
+
+// scores saturate towards the maximum, so recent results still count after long searches
+fn apply_bonus(score: &mut i32, bonus: i32) {
+    *score += bonus - *score * bonus.abs() / MAX_HISTORY;
+}
+
+fn move_index(mov: &Move) -> usize {
+    mov.from.idx as usize * 64 + mov.to.idx as usize
+}
+
+fn butterfly_index(position: &Board, mov: &Move) -> usize {
+    let color = if position.turn == Color::WHITE { 0 } else { 1 };
+    color * 64 * 64 + move_index(mov)
+}
+
+fn continuation_index(position: &Board, previous: Option<Move>, mov: &Move) -> Option<usize> {
+    let previous = previous?;
+    let previous_piece = piece_index(position.piece_type_at(previous.to.idx as i32)?);
+    let piece = piece_index(position.piece_type_at(mov.from.idx as i32)?);
+
+    let previous_key = previous_piece * 64 + previous.to.idx as usize;
+    let key = piece * 64 + mov.to.idx as usize;
+    Some(previous_key * PIECE_SQUARES + key)
+}
+
+fn piece_index(piece_type: &PieceType) -> usize {
+    PieceType::PIECE_TYPES.iter().position(|p| *p == piece_type).unwrap()
+}
@@ -0,0 +1,119 @@
+use crate::engine::bb;
+use crate::engine::bb::BitIterator;
+use crate::engine::board::{Board, Placement};
+use crate::engine::eval;
+use crate::engine::params::EvalParams;
+use crate::engine::piece::PieceType;
+
+// all the pieces but pawns and kings, as at the start
+const START_MATERIAL: i32 = 3100;
+
+// king safety from white's perspective; it matters only while there's material left to attack
+pub fn evaluate(params: &EvalParams, board: &Board) -> i32 {
+    let (white, black) = trace(params, board);
+    white - black
+}
+
+// each side's king safety, positive for the side
+pub fn trace(params: &EvalParams, board: &Board) -> (i32, i32) {
+    let rights = &board.castle_rights;
+    let white = evaluate_side(params, &board.placement, rights.kingside_w, rights.queenside_w);
+    let black = evaluate_side(params, &board.placement.mirror(), rights.kingside_b, rights.queenside_b);
+
+    (white, black)
+}
+
+// scores the white king's safety
+fn evaluate_side(params: &EvalParams, p: &Placement, kingside: bool, queenside: bool) -> i32 {
+    let king = bb::to_sq(p.kings & p.white);
+    let (king_rank, king_file) = bb::to_rank_file(king);
+
+    let mut eval = 0;
+    if kingside {
+        eval += params.castling_right;
+    }
+    if queenside {
+        eval += params.castling_right;
+    }
+
+    // files next to the king, and the king's own
+    let own_pawns = p.pawns & p.white;
+    let their_pawns = p.pawns & p.black;
+    for file in (king_file - 1).max(0)..=(king_file + 1).min(7) {
+        let file_mask = bb::FILE_A << file;
+
+        // a shield is only any use to a king still on its back ranks
+        if king_rank <= 1 {
+            let shield = own_pawns & file_mask;
+            eval += if bb::has_bit(shield, king + 8 + file - king_file) {
+                params.shield[0]
+            } else if king_rank == 0 && bb::has_bit(shield, king + 16 + file - king_file) {
+                params.shield[1]
+            } else {
+                params.shield_missing
+            };
+        }
+
+        // the nearest enemy pawn in front of the king on this file
+        if let Some(storm_sq) = BitIterator::from(their_pawns & file_mask).find(|sq| *sq > king) {
+            let distance = bb::to_rank_file(storm_sq).0 - king_rank;
+            if (distance as usize) < params.storm.len() {
+                eval += params.storm[distance as usize];
+            }
+        }
+
+        if 0 == p.pawns & file_mask {
+            eval += params.king_open_file;
+        } else if 0 == own_pawns & file_mask {
+            eval += params.king_semi_open_file;
+        }
+    }
+
+    eval - attack_danger(params, p, king)
+}
+
+// attacks on the king and the squares around it, including those a rank further in front
+fn attack_danger(params: &EvalParams, p: &Placement, king: i32) -> i32 {
+    let ring = bb::KING_MOVES[king as usize] | bb::set_bit(0, king);
+    let zone = ring | ring << 8;
+    let occupied = p.white | p.black;
+
+    let mut attackers = 0;
+    let mut weight = 0;
+    let mut count_attacks = |attacks: u64, attack_weight: i32| {
+        let zone_attacks = (attacks & zone).count_ones() as i32;
+        if zone_attacks > 0 {
+            attackers += 1;
+            weight += attack_weight * zone_attacks;
+        }
+    };
+
+    for sq in BitIterator::from(p.knights & p.black) {
+        count_attacks(bb::KNIGHT_MOVES[sq as usize], params.king_attack[0]);
+    }
+    for sq in BitIterator::from(p.bishops & p.black) {
+        count_attacks(bb::bishop_attacks(sq, occupied), params.king_attack[1]);
+    }
+    for sq in BitIterator::from(p.rooks & p.black) {
+        count_attacks(bb::rook_attacks(sq, occupied), params.king_attack[2]);
+    }
+    for sq in BitIterator::from(p.queens & p.black) {
+        count_attacks(bb::bishop_attacks(sq, occupied) | bb::rook_attacks(sq, occupied), params.king_attack[3]);
+    }
+
+    // weighted by how much attacking material remains
+    let scale = &params.attackers_scale;
+    let danger = weight * scale[attackers.min(scale.len() - 1)] / 100;
+    danger * material(params, p).min(START_MATERIAL) / START_MATERIAL
+}
+
+// black's pieces, not counting pawns
+fn material(params: &EvalParams, p: &Placement) -> i32 {
+    let mut material = 0;
+    material += eval::piece_value(params, &PieceType::KNIGHT) * (p.knights & p.black).count_ones() as i32;
+    material += eval::piece_value(params, &PieceType::BISHOP) * (p.bishops & p.black).count_ones() as i32;
+    material += eval::piece_value(params, &PieceType::ROOK) * (p.rooks & p.black).count_ones() as i32;
+    material += eval::piece_value(params, &PieceType::QUEEN) * (p.queens & p.black).count_ones() as i32;
+
+    material
+}
@@ -0,0 +1,106 @@
+use crate::engine::bb;
+use crate::engine::bb::BitIterator;
+use crate::engine::board::{Board, Placement};
+use crate::engine::params::EvalParams;
+
+const RANK_1: u64 = 0xff;
+
+// mobility and piece activity from white's perspective, as (middlegame, endgame)
+pub fn evaluate(params: &EvalParams, board: &Board) -> (i32, i32) {
+    let ((white_mg, white_eg), (black_mg, black_eg)) = trace(params, board);
+    (white_mg - black_mg, white_eg - black_eg)
+}
+
+// each side's mobility and activity, positive for the side
+pub fn trace(params: &EvalParams, board: &Board) -> ((i32, i32), (i32, i32)) {
+    let rights = &board.castle_rights;
+    let white = evaluate_side(params, &board.placement, rights.kingside_w || rights.queenside_w);
+    let black = evaluate_side(params, &board.placement.mirror(), rights.kingside_b || rights.queenside_b);
+
+    (white, black)
+}
+
+// scores the white pieces
+fn evaluate_side(params: &EvalParams, p: &Placement, can_castle: bool) -> (i32, i32) {
+    let occupied = p.white | p.black;
+    let own_pawns = p.pawns & p.white;
+    let their_pawns = p.pawns & p.black;
+
+    // squares attacked by enemy pawns aren't safe to move to
+    let their_pawn_attacks = (their_pawns >> 7 & !bb::FILE_A) | (their_pawns >> 9 & !bb::FILE_H);
+    let safe = !p.white & !their_pawn_attacks;
+
+    let mut score = (0, 0);
+    let mut add = |(mg, eg): (i32, i32), times: i32| {
+        score.0 += mg * times;
+        score.1 += eg * times;
+    };
+
+    for sq in BitIterator::from(p.knights & p.white) {
+        let squares = (bb::KNIGHT_MOVES[sq as usize] & safe).count_ones() as i32;
+        add(params.mobility[0], squares - params.mobility_squares[0]);
+
+        // on the enemy's side of the board, defended by a pawn, with no enemy pawns left to chase it
+        let (rank, file) = bb::to_rank_file(sq);
+        let is_defended = 0 != own_pawns & bb::pawn_attackers(sq, true);
+        if (3..=5).contains(&rank) && is_defended {
+            let ahead = !0 << ((rank + 1) * 8);
+            if 0 == their_pawns & bb::adjacent_files(file) & ahead {
+                add(params.knight_outpost, 1);
+            }
+        }
+    }
+
+    for sq in BitIterator::from(p.bishops & p.white) {
+        let squares = (bb::bishop_attacks(sq, occupied) & safe).count_ones() as i32;
+        add(params.mobility[1], squares - params.mobility_squares[1]);
+
+        let color = if bb::has_bit(bb::DARK_SQUARES, sq) { bb::DARK_SQUARES } else { !bb::DARK_SQUARES };
+        add(params.bad_bishop, (own_pawns & color).count_ones() as i32);
+
+        // a bishop taking a pawn on the edge can be shut in by the pawn beside it
+        let is_trapped = (sq == 48 && bb::has_bit(their_pawns, 41)) || (sq == 55 && bb::has_bit(their_pawns, 46));
+        if is_trapped {
+            add(params.trapped_bishop, 1);
+        }
+    }
+
+    if (p.bishops & p.white).count_ones() >= 2 {
+        add(params.bishop_pair, 1);
+    }
+
+    let king = bb::to_sq(p.kings & p.white);
+    let their_king = bb::to_sq(p.kings & p.black);
+    for sq in BitIterator::from(p.rooks & p.white) {
+        let squares = (bb::rook_attacks(sq, occupied) & safe).count_ones() as i32;
+        add(params.mobility[2], squares - params.mobility_squares[2]);
+
+        let (rank, file) = bb::to_rank_file(sq);
+        let file_mask = bb::FILE_A << file;
+        if 0 == p.pawns & file_mask {
+            add(params.rook_open_file, 1);
+        } else if 0 == own_pawns & file_mask {
+            add(params.rook_semi_open_file, 1);
+        }
+
+        // the seventh rank is worth having when there are pawns on it, or the king is behind it
+        let is_seventh_useful = their_king >= 56 || 0 != their_pawns & RANK_1 << 48;
+        if rank == 6 && is_seventh_useful {
+            add(params.rook_on_seventh, 1);
+        }
+
+        // the king stepped towards the rook's corner without castling, and can't anymore
+        let (king_rank, king_file) = bb::to_rank_file(king);
+        let is_shut_in = (file > king_file && king_file >= 5) || (file < king_file && king_file <= 2);
+        if !can_castle && rank == 0 && king_rank == 0 && is_shut_in {
+            add(params.trapped_rook, 1);
+        }
+    }
+
+    for sq in BitIterator::from(p.queens & p.white) {
+        let attacks = bb::bishop_attacks(sq, occupied) | bb::rook_attacks(sq, occupied);
+        add(params.mobility[3], (attacks & safe).count_ones() as i32 - params.mobility_squares[3]);
+    }
+
+    score
+}
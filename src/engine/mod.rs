@@ -1,20 +1,44 @@
-use std::collections::HashMap;
 use std::sync::{Arc, Mutex, mpsc};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 
 use crate::engine::board::Board;
+use crate::engine::book::Book;
 use crate::engine::mov::Move;
-use crate::engine::search::SearchStats;
+use crate::engine::nnue::{Accumulator, Network};
+use crate::engine::params::EvalParams;
+use crate::engine::psqt::Psqt;
+use crate::engine::search::{SearchResult, SearchStats};
+use crate::engine::tt::TranspositionTable;
 
+pub mod book;
 pub mod mov;
+pub mod pgn;
+pub mod selfplay;
+pub mod tune;
 mod bb;
 mod board;
+mod endgame;
 mod eval;
 mod gen;
 mod hash;
+mod history;
+mod king;
+mod mobility;
+mod nnue;
+mod params;
+mod pawns;
+mod picker;
 mod piece;
+mod psqt;
 mod search;
+mod see;
 mod square;
+mod time;
+mod tt;
+
+const DEFAULT_HASH_MB: i32 = 64;
+const MAX_HASH_MB: i32 = 65536;
 
 #[allow(dead_code)]
 pub enum LogLevel {
@@ -41,30 +65,77 @@ pub struct GoParams {
 
 pub struct Engine {
     state: Arc<Mutex<EngineState>>,
+    stop: Arc<AtomicBool>,
     num_cpus: usize,
+    book: Option<Book>,
+    own_book: bool,
+    book_best_move: bool,
 }
 
 
 pub struct EngineState {
     callbacks: Callbacks,
     position: Board,
-    table: Arc<Mutex<HashMap<u64, Transposition>>>,
+    table: Arc<TranspositionTable>,
 }
 
 
 pub struct Callbacks {
     pub log_fn: fn(LogLevel, &str),
-    pub best_move_fn: fn(&Move),
+    pub best_move_fn: fn(Option<&Move>),
+    pub info_fn: fn(&SearchInfo),
 }
 
 
-#[derive(Clone)]
-pub struct Transposition {
-    eval: Option<i32>,
-    eval_depth: Option<i32>,
-    q_eval: Option<i32>,
-    q_depth: Option<i32>,
-    best_move: Option<Move>,
+pub enum Score {
+    Cp(i32),
+    Mate(i32),
+}
+
+
+pub struct SearchInfo {
+    pub depth: i32,
+    pub seldepth: i32,
+    pub score: Score,
+    pub nodes: u64,
+    pub nps: u64,
+    pub time_ms: u64,
+    pub hashfull: usize,
+    pub pv: Vec<Move>,
+}
+
+
+// one evaluation term for each side, as (middlegame, endgame) and positive for the side
+pub struct EvalTerm {
+    pub name: &'static str,
+    pub white: (i32, i32),
+    pub black: (i32, i32),
+}
+
+
+// the static evaluation broken down by term, with the final eval from white's perspective
+pub struct EvalTrace {
+    pub terms: Vec<EvalTerm>,
+    pub phase: i32,
+    pub max_phase: i32,
+    pub scale: i32,
+    pub max_scale: i32,
+    pub special: Option<&'static str>,
+    pub network: bool,
+    pub eval: i32,
+}
+
+
+pub enum OptionType {
+    Check(bool),
+    Spin(i32, i32, i32),
+    String(&'static str),
+}
+
+
+pub struct EngineOption {
+    pub name: &'static str,
+    pub option_type: OptionType,
 }
 
 
@@ -73,18 +144,147 @@ impl Engine {
         let state = EngineState {
             callbacks,
             position: Board::start_pos(),
-            table: Arc::new(Mutex::new(HashMap::new())),
+            table: Arc::new(TranspositionTable::new(DEFAULT_HASH_MB as usize)),
         };
 
         Engine {
             state: Arc::new(Mutex::new(state)),
+            stop: Arc::new(AtomicBool::new(false)),
             num_cpus: num_cpus::get(),
+            book: None,
+            own_book: false,
+            book_best_move: false,
+        }
+    }
+
+    pub fn options(&self) -> Vec<EngineOption> {
+        vec![
+            EngineOption { name: "Hash", option_type: OptionType::Spin(DEFAULT_HASH_MB, 1, MAX_HASH_MB) },
+            EngineOption { name: "OwnBook", option_type: OptionType::Check(false) },
+            EngineOption { name: "BookFile", option_type: OptionType::String("<empty>") },
+            EngineOption { name: "BookBestMove", option_type: OptionType::Check(false) },
+            EngineOption { name: "EvalFile", option_type: OptionType::String("<empty>") },
+            EngineOption { name: "UseNNUE", option_type: OptionType::Check(false) },
+            EngineOption { name: "NNUEFile", option_type: OptionType::String("<empty>") },
+        ]
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) {
+
+        // option names are not case sensitive
+        match &name.to_lowercase()[..] {
+            "hash" => self.resize_table(value),
+            "ownbook" => self.own_book = value == "true",
+            "bookfile" => self.load_book(value),
+            "bookbestmove" => self.book_best_move = value == "true",
+            "evalfile" => self.load_eval_params(value),
+            "usennue" => self.use_network(value == "true"),
+            "nnuefile" => self.load_network(value),
+            _ => self.log(LogLevel::INFO, &format!("unknown option {}", name)),
+        }
+    }
+
+    fn resize_table(&mut self, value: &str) {
+        match value.parse::<i32>() {
+            Ok(size_mb) if (1..=MAX_HASH_MB).contains(&size_mb) => {
+                self.state.lock().unwrap().table = Arc::new(TranspositionTable::new(size_mb as usize));
+            }
+            _ => self.log(LogLevel::INFO, &format!("invalid hash size {}", value)),
+        }
+    }
+
+    fn load_book(&mut self, path: &str) {
+        self.book = None;
+        if path.is_empty() || path == "<empty>" {
+            return;
+        }
+
+        match Book::open(path) {
+            Ok(book) => {
+                self.log(LogLevel::DEBUG, &format!("loaded {} book entries from {}", book.len(), path));
+                self.book = Some(book);
+            }
+            Err(e) => self.log(LogLevel::INFO, &format!("failed to load book {}: {}", path, e)),
+        }
+    }
+
+    // an empty path goes back to the built in weights
+    fn load_eval_params(&mut self, path: &str) {
+        if path.is_empty() || path == "<empty>" {
+            self.set_eval_params(EvalParams::default());
+            self.log(LogLevel::DEBUG, "using the default eval parameters");
+            return;
+        }
+
+        match EvalParams::load(path) {
+            Ok(params) => {
+                self.set_eval_params(params);
+                self.log(LogLevel::DEBUG, &format!("loaded eval parameters from {}", path));
+            }
+            Err(e) => self.log(LogLevel::INFO, &format!("failed to load eval parameters {}: {}", path, e)),
+        }
+    }
+
+    fn set_eval_params(&mut self, params: EvalParams) {
+        params::set(params);
+
+        // incremental scores of the current position were made with the old weights, and so were
+        // the evaluations stored in the table
+        let mut state = self.state.lock().unwrap();
+        state.position.psqt = params::with(|params| Psqt::of(params, &state.position.placement));
+        state.table.clear();
+    }
+
+    fn use_network(&mut self, enabled: bool) {
+        nnue::set_enabled(enabled);
+        self.refresh_network();
+    }
+
+    fn load_network(&mut self, path: &str) {
+        if path.is_empty() || path == "<empty>" {
+            nnue::set(None);
+        } else {
+            match Network::load(path) {
+                Ok(network) => {
+                    nnue::set(Some(network));
+                    self.log(LogLevel::DEBUG, &format!("loaded network from {}", path));
+                }
+                Err(e) => self.log(LogLevel::INFO, &format!("failed to load network {}: {}", path, e)),
+            }
         }
+
+        self.refresh_network();
+    }
+
+    // the current position's accumulator is rebuilt for the network now in use, if any
+    fn refresh_network(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        let accumulator = nnue::with(|network| network.map(|n| Arc::new(Accumulator::of(n, &state.position.placement))));
+        state.position.accumulator = accumulator;
+        state.table.clear();
+    }
+
+    pub fn save_eval_params(&self, path: &str) {
+        match params::with(|params| params.save(path)) {
+            Ok(()) => self.log(LogLevel::DEBUG, &format!("saved eval parameters to {}", path)),
+            Err(e) => self.log(LogLevel::INFO, &format!("failed to save eval parameters {}: {}", path, e)),
+        }
+    }
+
+    fn probe_book(&self) -> Option<Move> {
+        let book = self.book.as_ref()?;
+        let position = self.state.lock().unwrap().position.clone();
+
+        book.choose_move(&position, self.book_best_move)
+    }
+
+    fn log(&self, level: LogLevel, msg: &str) {
+        (self.state.lock().unwrap().callbacks.log_fn)(level, msg);
     }
 
     pub fn reset(&mut self) {
         self.set_start_pos(Vec::new());
-        self.state.lock().unwrap().table.lock().unwrap().clear();
+        self.state.lock().unwrap().table.clear();
     }
 
     pub fn set_start_pos(&mut self, moves: Vec<Move>) {
@@ -105,21 +305,40 @@ impl Engine {
         self.state.lock().unwrap().position = position;
     }
 
+    // the static evaluation of the current position, term by term
+    pub fn eval_trace(&self) -> EvalTrace {
+        eval::trace(&self.state.lock().unwrap().position)
+    }
+
     pub fn go(&mut self, p: GoParams) {
 
+        // play straight from the opening book when possible
+        if self.own_book && p.search_moves.is_empty() && !p.infinite {
+            if let Some(mov) = self.probe_book() {
+                self.log(LogLevel::DEBUG, &format!("book move {}", mov.uci()));
+                (self.state.lock().unwrap().callbacks.best_move_fn)(Some(&mov));
+                return;
+            }
+        }
+
         eprintln!();
         eprintln!("---- searching with {} threads", self.num_cpus);
 
         let mut threads = Vec::new();
-        let (tx_stats, rx_stats) = mpsc::channel();
+        let (tx_result, rx_result) = mpsc::channel();
+
+        // entries from earlier searches are kept, but are the first to be replaced
+        self.state.lock().unwrap().table.new_search();
+        self.stop.store(false, Ordering::Relaxed);
 
         // start search threads
         for i in 0..self.num_cpus {
             let state = Arc::clone(&self.state);
             let params = p.clone();
-            let tx_stats_i = tx_stats.clone();
+            let stop = Arc::clone(&self.stop);
+            let tx_result_i = tx_result.clone();
             let handle = thread::spawn(move || {
-                search::search(state, params, i, tx_stats_i.clone());
+                search::search(state, params, i, stop, tx_result_i.clone());
             });
 
             threads.push(handle);
@@ -136,34 +355,34 @@ impl Engine {
                 }
             }
 
-            // get the PV from the transposition table
-            let state = state.lock().unwrap();
-            let table = state.table.lock().unwrap();
-            let transposition = table.get(&state.position.hash).expect("no root transposition");
-            let mov = transposition.best_move.expect("no move");
-
-            // gather search statistics
+            // gather search results and statistics
+            let results: Vec<SearchResult> = rx_result.try_iter().collect();
             let mut stats = SearchStats::new();
-            for thread_stats in rx_stats.iter() {
-                stats.combine(&thread_stats);
+            for result in results.iter() {
+                stats.combine(&result.stats);
             }
 
+            // choose between the threads' best moves; there are none when the game is already over
+            let state = state.lock().unwrap();
+            let mov = search::vote(&results);
+
             // report statistics to std error
-            eprintln!("---- {}", mov.uci());
+            eprintln!("---- {}", mov.map_or(String::from("none"), |m| m.uci()));
             eprintln!("nodes_visited: {}", stats.nodes_visited);
             eprintln!("tt_hits: {}", stats.tt_hits);
             eprintln!("tt_waste: {}", stats.tt_waste);
+            eprintln!("hashfull: {}", state.table.hashfull());
             eprintln!("time_elapsed (ms): {}", stats.time_elapsed.as_millis());
             eprintln!("max_depth: {}", stats.max_depth);
             eprintln!("nps: {}", stats.nps());
 
-            (state.callbacks.best_move_fn)(&mov);
+            (state.callbacks.best_move_fn)(mov.as_ref());
         });
     }
 
     pub fn stop(&self) {
         (self.state.lock().unwrap().callbacks.log_fn)(LogLevel::DEBUG, "stopping");
-        // TODO stop searching
+        self.stop.store(true, Ordering::Relaxed);
     }
 
     pub fn update_log_fn(&mut self, log_fn: fn(LogLevel, &str)) {
@@ -89,6 +89,35 @@ impl Move {
         None
     }
 
+    // packed into 16 bits the same way as polyglot books: to (6), from (6), promotion (3)
+    pub fn pack(&self) -> u16 {
+        let promotion = match self.promotion {
+            Some(&PieceType::KNIGHT) => 1,
+            Some(&PieceType::BISHOP) => 2,
+            Some(&PieceType::ROOK) => 3,
+            Some(&PieceType::QUEEN) => 4,
+            _ => 0,
+        };
+
+        (promotion << 12) | ((self.from.idx as u16) << 6) | self.to.idx as u16
+    }
+
+    pub fn unpack(raw: u16) -> Move {
+        let promotion = match (raw >> 12) & 0x7 {
+            1 => Some(&PieceType::KNIGHT),
+            2 => Some(&PieceType::BISHOP),
+            3 => Some(&PieceType::ROOK),
+            4 => Some(&PieceType::QUEEN),
+            _ => None,
+        };
+
+        Move {
+            from: Square::SQUARES[((raw >> 6) & 0x3f) as usize],
+            to: Square::SQUARES[(raw & 0x3f) as usize],
+            promotion,
+        }
+    }
+
     pub fn uci(&self) -> String {
         let mut repr = String::with_capacity(5);
         repr.push_str(self.from.symbol);
@@ -0,0 +1,216 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::engine::bb::BitIterator;
+use crate::engine::board::{Board, Color, Placement};
+
+// a (768 -> HIDDEN) x 2 -> 1 network: one input for each piece type and color on each square, seen
+// from both sides, into a hidden layer shared by both perspectives; there are no king buckets, so
+// networks trained with them, or with another hidden size, can't be loaded
+pub const HIDDEN: usize = 256;
+const INPUTS: usize = 768;
+
+// quantization of the hidden layer and the output weights, and centipawns per unit of output
+const QA: i32 = 255;
+const QB: i32 = 64;
+const SCALE: i32 = 400;
+
+lazy_static! {
+    static ref NETWORK: RwLock<Option<Arc<Network>>> = RwLock::new(None);
+}
+
+// a loaded network is only used once switched on
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+// bumped whenever the network is replaced or switched on or off
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static CACHED: RefCell<(usize, Option<Arc<Network>>)> = const { RefCell::new((0, None)) };
+}
+
+// weights are stored as they're used, so that each update is a run of adds over one row
+pub struct Network {
+    feature_weights: Vec<[i16; HIDDEN]>,
+    feature_bias: [i16; HIDDEN],
+    output_weights: [[i16; HIDDEN]; 2],
+    output_bias: i16,
+}
+
+// the hidden layer before activation, from each side's perspective
+#[derive(Clone)]
+#[repr(align(64))]
+pub struct Accumulator {
+    white: [i16; HIDDEN],
+    black: [i16; HIDDEN],
+}
+
+impl Network {
+
+    // raw little-endian i16s and nothing else, in this order:
+    //   input weights, INPUTS rows of HIDDEN, feature color * 384 + piece * 64 + square, where
+    //     color is 0 for the perspective's own pieces, pieces go pawn to king, and squares a1 to
+    //     h8 as white sees them, flipped vertically for black
+    //   input biases, HIDDEN
+    //   output weights, HIDDEN for the side to move then HIDDEN for the other side
+    //   output bias, 1
+    // anything else is rejected by its size, including files padded by the trainer
+    pub fn load(path: &str) -> io::Result<Network> {
+        let bytes = fs::read(path)?;
+        let expected = 2 * (INPUTS * HIDDEN + HIDDEN + 2 * HIDDEN + 1);
+        if bytes.len() != expected {
+            let msg = format!("expected a {}-input, {}-hidden network of {} bytes, found {}", INPUTS, HIDDEN, expected, bytes.len());
+            return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+        }
+
+        let mut values = bytes.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]]));
+        let mut read_row = || {
+            let mut row = [0; HIDDEN];
+            row.iter_mut().for_each(|v| *v = values.next().unwrap());
+            row
+        };
+
+        let feature_weights = (0..INPUTS).map(|_| read_row()).collect();
+        let feature_bias = read_row();
+        let output_weights = [read_row(), read_row()];
+        let output_bias = values.next().unwrap();
+
+        Ok(Network {
+            feature_weights,
+            feature_bias,
+            output_weights,
+            output_bias,
+        })
+    }
+}
+
+impl Accumulator {
+    pub fn of(network: &Network, placement: &Placement) -> Self {
+        let mut accumulator = Self {
+            white: network.feature_bias,
+            black: network.feature_bias,
+        };
+
+        for (piece, pieces) in by_piece(placement).iter().enumerate() {
+            for sq in BitIterator::from(pieces & placement.white) {
+                accumulator.add(network, piece, true, sq);
+            }
+            for sq in BitIterator::from(pieces & placement.black) {
+                accumulator.add(network, piece, false, sq);
+            }
+        }
+
+        accumulator
+    }
+
+    // only the pieces that differ between the placements are updated
+    pub fn update(&self, network: &Network, from: &Placement, to: &Placement) -> Self {
+        let mut accumulator = self.clone();
+        for (piece, (before, after)) in by_piece(from).iter().zip(by_piece(to).iter()).enumerate() {
+            let sides = [
+                (true, before & from.white, after & to.white),
+                (false, before & from.black, after & to.black),
+            ];
+
+            for (white, before, after) in sides.iter() {
+                for sq in BitIterator::from(before & !after) {
+                    accumulator.remove(network, piece, *white, sq);
+                }
+                for sq in BitIterator::from(after & !before) {
+                    accumulator.add(network, piece, *white, sq);
+                }
+            }
+        }
+
+        accumulator
+    }
+
+    pub fn mirror(&self) -> Self {
+        Self {
+            white: self.black,
+            black: self.white,
+        }
+    }
+
+    fn add(&mut self, network: &Network, piece: usize, white: bool, sq: i32) {
+        let (white_feature, black_feature) = features(piece, white, sq);
+        add_row(&mut self.white, &network.feature_weights[white_feature], 1);
+        add_row(&mut self.black, &network.feature_weights[black_feature], 1);
+    }
+
+    fn remove(&mut self, network: &Network, piece: usize, white: bool, sq: i32) {
+        let (white_feature, black_feature) = features(piece, white, sq);
+        add_row(&mut self.white, &network.feature_weights[white_feature], -1);
+        add_row(&mut self.black, &network.feature_weights[black_feature], -1);
+    }
+}
+
+// written over fixed-size rows so the compiler can vectorize it
+fn add_row(values: &mut [i16; HIDDEN], row: &[i16; HIDDEN], sign: i16) {
+    for (v, w) in values.iter_mut().zip(row.iter()) {
+        *v = v.wrapping_add(sign.wrapping_mul(*w));
+    }
+}
+
+// the input for a piece as seen by white, and by black with the board flipped and colors swapped
+fn features(piece: usize, white: bool, sq: i32) -> (usize, usize) {
+    let sq = sq as usize;
+    let (white_color, black_color) = if white { (0, 1) } else { (1, 0) };
+    (white_color * 384 + piece * 64 + sq, black_color * 384 + piece * 64 + (sq ^ 56))
+}
+
+fn by_piece(p: &Placement) -> [u64; 6] {
+    [p.pawns, p.knights, p.bishops, p.rooks, p.queens, p.kings]
+}
+
+// replaces the network for every thread; each picks it up at its next move or evaluation
+pub fn set(network: Option<Network>) {
+    *NETWORK.write().unwrap() = network.map(Arc::new);
+    GENERATION.fetch_add(1, Ordering::AcqRel);
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Release);
+    GENERATION.fetch_add(1, Ordering::AcqRel);
+}
+
+// the network in use, if any, without taking the lock unless it's changed
+pub fn with<R>(f: impl FnOnce(Option<&Network>) -> R) -> R {
+    CACHED.with(|cached| {
+        let generation = GENERATION.load(Ordering::Acquire);
+        if cached.borrow().0 != generation {
+            if let Ok(mut cached) = cached.try_borrow_mut() {
+                let network = if ENABLED.load(Ordering::Acquire) { NETWORK.read().unwrap().clone() } else { None };
+                *cached = (generation, network);
+            }
+        }
+
+        f(cached.borrow().1.as_deref())
+    })
+}
+
+// from white's perspective, when there's a network and the board has kept its accumulator
+pub fn evaluate(board: &Board) -> Option<i32> {
+    with(|network| {
+        let network = network?;
+        let accumulator = board.accumulator.as_ref()?;
+
+        let (us, them) = match board.turn {
+            Color::WHITE => (&accumulator.white, &accumulator.black),
+            Color::BLACK => (&accumulator.black, &accumulator.white),
+        };
+
+        let output = dot(us, &network.output_weights[0]) + dot(them, &network.output_weights[1]);
+        let eval = (output + network.output_bias as i32) * SCALE / (QA * QB);
+
+        Some(if board.turn == Color::WHITE { eval } else { -eval })
+    })
+}
+
+// clipped relu activation of the hidden layer into the output
+fn dot(values: &[i16; HIDDEN], weights: &[i16; HIDDEN]) -> i32 {
+    values.iter().zip(weights.iter()).map(|(v, w)| (*v as i32).clamp(0, QA) * *w as i32).sum()
+}
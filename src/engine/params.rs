@@ -0,0 +1,376 @@
+use std::cell::RefCell;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+lazy_static! {
+    static ref PARAMS: RwLock<Arc<EvalParams>> = RwLock::new(Arc::new(EvalParams::default()));
+}
+
+// bumped whenever new parameters are set, so each thread knows to pick them up
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static CACHED: RefCell<(usize, Arc<EvalParams>)> = RefCell::new((0, PARAMS.read().unwrap().clone()));
+}
+
+// every evaluation weight, with a name as it appears in a parameter file and its default value
+macro_rules! eval_params {
+    ($($name:ident: $t:ty = $default:expr,)*) => {
+        #[derive(Clone)]
+        pub struct EvalParams {
+            $(pub $name: $t,)*
+        }
+
+        impl Default for EvalParams {
+            fn default() -> Self {
+                Self {
+                    $($name: $default,)*
+                }
+            }
+        }
+
+        impl EvalParams {
+            fn write_params(&self, out: &mut impl Write) -> io::Result<()> {
+                $(write_param(out, stringify!($name), &self.$name)?;)*
+                Ok(())
+            }
+
+            // every weight in one list, in the order they're declared
+            pub fn values(&self) -> Vec<i32> {
+                let mut values = Vec::new();
+                $(self.$name.write(&mut values);)*
+                values
+            }
+
+            pub fn set_values(&mut self, values: &[i32]) {
+                let mut start = 0;
+                $(
+                    let len = self.$name.len();
+                    self.$name.read(&values[start..start + len]);
+                    start += len;
+                )*
+                assert_eq!(start, values.len(), "wrong number of parameter values");
+            }
+
+            fn param_mut(&mut self, name: &str) -> Option<&mut dyn Param> {
+                match name {
+                    $(stringify!($name) => Some(&mut self.$name),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+eval_params! {
+
+    // material for exchanges and move ordering, by piece type from pawn to queen
+    piece_values: [i32; 5] = [100, 300, 300, 500, 900],
+
+    // game phase each piece type counts for, from pawn to king
+    phase: [i32; 6] = [0, 1, 1, 2, 4, 0],
+
+    // piece values and tables, middlegame then endgame, from pawn to king; tables start from a8
+    mg_value: [i32; 6] = [82, 337, 365, 477, 1025, 0],
+    eg_value: [i32; 6] = [94, 281, 297, 512, 936, 0],
+    mg_table: [[i32; 64]; 6] = MG_TABLE,
+    eg_table: [[i32; 64]; 6] = EG_TABLE,
+
+    // pawn structure as (middlegame, endgame), or by rank from the side's own perspective
+    doubled: (i32, i32) = (-10, -20),
+    isolated: (i32, i32) = (-10, -15),
+    backward: (i32, i32) = (-8, -10),
+    connected: [i32; 8] = [0, 5, 7, 10, 20, 35, 60, 0],
+    passed_mg: [i32; 8] = [0, 5, 10, 15, 30, 50, 90, 0],
+    passed_eg: [i32; 8] = [0, 10, 20, 35, 60, 100, 150, 0],
+
+    // per square of king distance to a passer's stop square, and a passer no king can catch
+    their_king_distance: i32 = 5,
+    own_king_distance: i32 = 2,
+    unstoppable: i32 = 500,
+
+    // king safety, middlegame only; attacks on the king zone by knight, bishop, rook and queen
+    king_attack: [i32; 4] = [20, 20, 40, 80],
+    attackers_scale: [i32; 8] = [0, 0, 50, 75, 88, 94, 97, 99],
+    shield: [i32; 2] = [15, 8],
+    shield_missing: i32 = -12,
+    storm: [i32; 4] = [0, -20, -12, -5],
+    king_open_file: i32 = -20,
+    king_semi_open_file: i32 = -10,
+    castling_right: i32 = 15,
+
+    // per safe square beyond a typical number of them, for knight, bishop, rook and queen
+    mobility: [(i32, i32); 4] = [(4, 4), (5, 5), (2, 4), (1, 2)],
+    mobility_squares: [i32; 4] = [4, 7, 7, 14],
+
+    // piece activity as (middlegame, endgame)
+    bishop_pair: (i32, i32) = (30, 50),
+    rook_open_file: (i32, i32) = (25, 10),
+    rook_semi_open_file: (i32, i32) = (12, 5),
+    rook_on_seventh: (i32, i32) = (20, 30),
+    knight_outpost: (i32, i32) = (25, 10),
+    trapped_rook: (i32, i32) = (-40, -10),
+    trapped_bishop: (i32, i32) = (-100, -100),
+    bad_bishop: (i32, i32) = (-3, -5),
+
+    // endgames; scale factors are out of 64
+    scale_opposite_bishops: i32 = 32,
+    scale_rook_bishop: i32 = 16,
+    known_win: i32 = 5000,
+    push_to_edge: i32 = 20,
+    push_to_corner: i32 = 30,
+    push_close: i32 = 10,
+}
+
+// a weight made of any number of values, written out flat
+trait Param {
+    fn len(&self) -> usize;
+    fn write(&self, values: &mut Vec<i32>);
+    fn read(&mut self, values: &[i32]);
+}
+
+impl Param for i32 {
+    fn len(&self) -> usize {
+        1
+    }
+
+    fn write(&self, values: &mut Vec<i32>) {
+        values.push(*self);
+    }
+
+    fn read(&mut self, values: &[i32]) {
+        *self = values[0];
+    }
+}
+
+impl Param for (i32, i32) {
+    fn len(&self) -> usize {
+        2
+    }
+
+    fn write(&self, values: &mut Vec<i32>) {
+        values.push(self.0);
+        values.push(self.1);
+    }
+
+    fn read(&mut self, values: &[i32]) {
+        *self = (values[0], values[1]);
+    }
+}
+
+impl<T: Param, const N: usize> Param for [T; N] {
+    fn len(&self) -> usize {
+        self.iter().map(|p| p.len()).sum()
+    }
+
+    fn write(&self, values: &mut Vec<i32>) {
+        for p in self.iter() {
+            p.write(values);
+        }
+    }
+
+    fn read(&mut self, values: &[i32]) {
+        let mut start = 0;
+        for p in self.iter_mut() {
+            let len = p.len();
+            p.read(&values[start..start + len]);
+            start += len;
+        }
+    }
+}
+
+impl EvalParams {
+
+    // weights not named in the file keep their defaults
+    pub fn load(path: &str) -> io::Result<EvalParams> {
+        let text = fs::read_to_string(path)?;
+        let invalid = |line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("bad parameter line: {}", line));
+
+        let mut params = EvalParams::default();
+        for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            let mut parts = line.split_whitespace();
+            let name = parts.next().unwrap();
+            let values = parts.map(|v| v.parse::<i32>()).collect::<Result<Vec<i32>, _>>().map_err(|_| invalid(line))?;
+
+            let param = params.param_mut(name).ok_or_else(|| invalid(line))?;
+            if values.len() != param.len() {
+                return Err(invalid(line));
+            }
+            param.read(&values);
+        }
+
+        Ok(params)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "# name values...")?;
+        self.write_params(&mut out)?;
+        out.flush()
+    }
+}
+
+fn write_param(out: &mut impl Write, name: &str, param: &dyn Param) -> io::Result<()> {
+    let mut values = Vec::new();
+    param.write(&mut values);
+
+    let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    writeln!(out, "{} {}", name, values.join(" "))
+}
+
+// replaces the parameters for every thread; each picks them up at its next evaluation
+pub fn set(params: EvalParams) {
+    *PARAMS.write().unwrap() = Arc::new(params);
+    GENERATION.fetch_add(1, Ordering::AcqRel);
+}
+
+pub fn generation() -> usize {
+    GENERATION.load(Ordering::Acquire)
+}
+
+// the current parameters, without taking the lock unless they've changed
+pub fn with<R>(f: impl FnOnce(&EvalParams) -> R) -> R {
+    CACHED.with(|cached| {
+        let generation = generation();
+        if cached.borrow().0 != generation {
+
+            // a nested call can't refresh; it keeps the old parameters until the next evaluation
+            if let Ok(mut cached) = cached.try_borrow_mut() {
+                *cached = (generation, PARAMS.read().unwrap().clone());
+            }
+        }
+
+        f(&cached.borrow().1)
+    })
+}
+
+// PeSTO's tables, laid out as seen by white
+const MG_TABLE: [[i32; 64]; 6] = [
+    [
+        0, 0, 0, 0, 0, 0, 0, 0,
+        98, 134, 61, 95, 68, 126, 34, -11,
+        -6, 7, 26, 31, 65, 56, 25, -20,
+        -14, 13, 6, 21, 23, 12, 17, -23,
+        -27, -2, -5, 12, 17, 6, 10, -25,
+        -26, -4, -4, -10, 3, 3, 33, -12,
+        -35, -1, -20, -23, -15, 24, 38, -22,
+        0, 0, 0, 0, 0, 0, 0, 0,
+    ],
+    [
+        -167, -89, -34, -49, 61, -97, -15, -107,
+        -73, -41, 72, 36, 23, 62, 7, -17,
+        -47, 60, 37, 65, 84, 129, 73, 44,
+        -9, 17, 19, 53, 37, 69, 18, 22,
+        -13, 4, 16, 13, 28, 19, 21, -8,
+        -23, -9, 12, 10, 19, 17, 25, -16,
+        -29, -53, -12, -3, -1, 18, -14, -19,
+        -105, -21, -58, -33, -17, -28, -19, -23,
+    ],
+    [
+        -29, 4, -82, -37, -25, -42, 7, -8,
+        -26, 16, -18, -13, 30, 59, 18, -47,
+        -16, 37, 43, 40, 35, 50, 37, -2,
+        -4, 5, 19, 50, 37, 37, 7, -2,
+        -6, 13, 13, 26, 34, 12, 10, 4,
+        0, 15, 15, 15, 14, 27, 18, 10,
+        4, 15, 16, 0, 7, 21, 33, 1,
+        -33, -3, -14, -21, -13, -12, -39, -21,
+    ],
+    [
+        32, 42, 32, 51, 63, 9, 31, 43,
+        27, 32, 58, 62, 80, 67, 26, 44,
+        -5, 19, 26, 36, 17, 45, 61, 16,
+        -24, -11, 7, 26, 24, 35, -8, -20,
+        -36, -26, -12, -1, 9, -7, 6, -23,
+        -45, -25, -16, -17, 3, 0, -5, -33,
+        -44, -16, -20, -9, -1, 11, -6, -71,
+        -19, -13, 1, 17, 16, 7, -37, -26,
+    ],
+    [
+        -28, 0, 29, 12, 59, 44, 43, 45,
+        -24, -39, -5, 1, -16, 57, 28, 54,
+        -13, -17, 7, 8, 29, 56, 47, 57,
+        -27, -27, -16, -16, -1, 17, -2, 1,
+        -9, -26, -9, -10, -2, -4, 3, -3,
+        -14, 2, -11, -2, -5, 2, 14, 5,
+        -35, -8, 11, 2, 8, 15, -3, 1,
+        -1, -18, -9, 10, -15, -25, -31, -50,
+    ],
+    [
+        -65, 23, 16, -15, -56, -34, 2, 13,
+        29, -1, -20, -7, -8, -4, -38, -29,
+        -9, 24, 2, -16, -20, 6, 22, -22,
+        -17, -20, -12, -27, -30, -25, -14, -36,
+        -49, -1, -27, -39, -46, -44, -33, -51,
+        -14, -14, -22, -46, -44, -30, -15, -27,
+        1, 7, -8, -64, -43, -16, 9, 8,
+        -15, 36, 12, -54, 8, -28, 24, 14,
+    ],
+];
+
+const EG_TABLE: [[i32; 64]; 6] = [
+    [
+        0, 0, 0, 0, 0, 0, 0, 0,
+        178, 173, 158, 134, 147, 132, 165, 187,
+        94, 100, 85, 67, 56, 53, 82, 84,
+        32, 24, 13, 5, -2, 4, 17, 17,
+        13, 9, -3, -7, -7, -8, 3, -1,
+        4, 7, -6, 1, 0, -5, -1, -8,
+        13, 8, 8, 10, 13, 0, 2, -7,
+        0, 0, 0, 0, 0, 0, 0, 0,
+    ],
+    [
+        -58, -38, -13, -28, -31, -27, -63, -99,
+        -25, -8, -25, -2, -9, -25, -24, -52,
+        -24, -20, 10, 9, -1, -9, -19, -41,
+        -17, 3, 22, 22, 22, 11, 8, -18,
+        -18, -6, 16, 25, 16, 17, 4, -18,
+        -23, -3, -1, 15, 10, -3, -20, -22,
+        -42, -20, -10, -5, -2, -20, -23, -44,
+        -29, -51, -23, -15, -22, -18, -50, -64,
+    ],
+    [
+        -14, -21, -11, -8, -7, -9, -17, -24,
+        -8, -4, 7, -12, -3, -13, -4, -14,
+        2, -8, 0, -1, -2, 6, 0, 4,
+        -3, 9, 12, 9, 14, 10, 3, 2,
+        -6, 3, 13, 19, 7, 10, -3, -9,
+        -12, -3, 8, 10, 13, 3, -7, -15,
+        -14, -18, -7, -1, 4, -9, -15, -27,
+        -23, -9, -23, -5, -9, -16, -5, -17,
+    ],
+    [
+        13, 10, 18, 15, 12, 12, 8, 5,
+        11, 13, 13, 11, -3, 3, 8, 3,
+        7, 7, 7, 5, 4, -3, -5, -3,
+        4, 3, 13, 1, 2, 1, -1, 2,
+        3, 5, 8, 4, -5, -6, -8, -11,
+        -4, 0, -5, -1, -7, -12, -8, -16,
+        -6, -6, 0, 2, -9, -9, -11, -3,
+        -9, 2, 3, -1, -5, -13, 4, -20,
+    ],
+    [
+        -9, 22, 22, 27, 27, 19, 10, 20,
+        -17, 20, 32, 41, 58, 25, 30, 0,
+        -20, 6, 9, 49, 47, 35, 19, 9,
+        3, 22, 24, 45, 57, 40, 57, 36,
+        -18, 28, 19, 47, 31, 34, 39, 23,
+        -16, -27, 15, 6, 9, 17, 10, 5,
+        -22, -23, -30, -16, -16, -23, -36, -32,
+        -33, -28, -22, -43, -5, -32, -20, -41,
+    ],
+    [
+        -74, -35, -18, -18, -11, 15, 4, -17,
+        -12, 17, 14, 17, 17, 38, 23, 11,
+        10, 17, 23, 15, 20, 45, 44, 13,
+        -8, 22, 24, 27, 26, 33, 26, 3,
+        -18, -4, 21, 24, 27, 23, 9, -11,
+        -19, -3, 11, 21, 23, 16, 7, -9,
+        -27, -11, 4, 13, 14, 4, -5, -17,
+        -53, -34, -21, -11, -28, -14, -24, -43,
+    ],
+];
@@ -0,0 +1,229 @@
+use std::cell::RefCell;
+
+use crate::engine::bb;
+use crate::engine::bb::BitIterator;
+use crate::engine::board::{Board, Color};
+use crate::engine::params;
+use crate::engine::params::EvalParams;
+
+const PAWN_TABLE_SIZE: usize = 1 << 14;
+
+lazy_static! {
+    static ref FRONT_SPAN: [u64; 64] = init_spans(0);
+    static ref PASSED_SPAN: [u64; 64] = init_spans(1);
+    static ref SUPPORT_SPAN: [u64; 64] = init_support_spans();
+}
+
+thread_local! {
+    static PAWN_TABLE: RefCell<PawnTable> = RefCell::new(PawnTable {
+        generation: params::generation(),
+        entries: vec![Entry::default(); PAWN_TABLE_SIZE],
+    });
+}
+
+// entries are only good for the parameters they were scored with
+struct PawnTable {
+    generation: usize,
+    entries: Vec<Entry>,
+}
+
+// pawn structure depends on nothing but the pawns, so it's cached by the pawn key
+#[derive(Copy, Clone, Default)]
+struct Entry {
+    key: u64,
+    mg: i32,
+    eg: i32,
+    passed_white: u64,
+    passed_black: u64,
+}
+
+// pawn structure and passed pawns from white's perspective, as (middlegame, endgame)
+pub fn evaluate(params: &EvalParams, board: &Board) -> (i32, i32) {
+    let entry = probe(params, board);
+    let eg = evaluate_passers(params, board, entry.passed_white, true) - evaluate_passers(params, board, entry.passed_black, false);
+
+    (entry.mg, entry.eg + eg)
+}
+
+// each side's pawns, positive for the side, without going through the cache
+pub fn trace(params: &EvalParams, board: &Board) -> ((i32, i32), (i32, i32)) {
+    let p = &board.placement;
+    let white = p.pawns & p.white;
+    let black = p.pawns & p.black;
+
+    let (white_mg, white_eg, passed_white) = evaluate_side(params, white, black);
+    let (black_mg, black_eg, passed_black) = evaluate_side(params, black.swap_bytes(), white.swap_bytes());
+    let white_eg = white_eg + evaluate_passers(params, board, passed_white, true);
+    let black_eg = black_eg + evaluate_passers(params, board, passed_black.swap_bytes(), false);
+
+    ((white_mg, white_eg), (black_mg, black_eg))
+}
+
+fn probe(params: &EvalParams, board: &Board) -> Entry {
+    PAWN_TABLE.with(|table| {
+        let mut table = table.borrow_mut();
+        let generation = params::generation();
+        if table.generation != generation {
+            table.generation = generation;
+            table.entries.iter_mut().for_each(|e| *e = Entry::default());
+        }
+
+        let i = (board.pawn_hash % PAWN_TABLE_SIZE as u64) as usize;
+        if table.entries[i].key != board.pawn_hash {
+            table.entries[i] = evaluate_structure(params, board);
+        }
+
+        table.entries[i]
+    })
+}
+
+fn evaluate_structure(params: &EvalParams, board: &Board) -> Entry {
+    let p = &board.placement;
+    let white = p.pawns & p.white;
+    let black = p.pawns & p.black;
+
+    // black is scored as white on a mirrored board
+    let (white_mg, white_eg, passed_white) = evaluate_side(params, white, black);
+    let (black_mg, black_eg, passed_black) = evaluate_side(params, black.swap_bytes(), white.swap_bytes());
+
+    Entry {
+        key: board.pawn_hash,
+        mg: white_mg - black_mg,
+        eg: white_eg - black_eg,
+        passed_white,
+        passed_black: passed_black.swap_bytes(),
+    }
+}
+
+// scores one side's pawns as if they were white, returning its passed pawns too
+fn evaluate_side(params: &EvalParams, own: u64, theirs: u64) -> (i32, i32, u64) {
+    let mut mg = 0;
+    let mut eg = 0;
+    let mut passed = 0;
+
+    for sq in BitIterator::from(own) {
+        let (rank, file) = bb::to_rank_file(sq);
+        let rank = rank as usize;
+        let adjacent = bb::adjacent_files(file);
+
+        // the pawn behind is the one that's doubled
+        let is_doubled = 0 != own & FRONT_SPAN[sq as usize];
+        if is_doubled {
+            mg += params.doubled.0;
+            eg += params.doubled.1;
+        }
+
+        let is_isolated = 0 == own & adjacent;
+        if is_isolated {
+            mg += params.isolated.0;
+            eg += params.isolated.1;
+        }
+
+        // defended by a pawn, or standing beside one
+        let is_supported = 0 != own & bb::pawn_attackers(sq, true);
+        let is_phalanx = 0 != own & adjacent & (0xff << (rank * 8));
+        if is_supported || is_phalanx {
+            mg += params.connected[rank];
+            eg += params.connected[rank] / 2;
+        }
+
+        // no neighbour can come up to support it, and advancing loses it to a pawn
+        if !is_isolated && !is_supported && !is_phalanx && rank < 7 {
+            let is_stop_attacked = 0 != theirs & bb::PAWN_ATTACKS[sq as usize + 8];
+            if 0 == own & SUPPORT_SPAN[sq as usize] && is_stop_attacked {
+                mg += params.backward.0;
+                eg += params.backward.1;
+            }
+        }
+
+        // nothing can stop it but pieces
+        if !is_doubled && 0 == theirs & PASSED_SPAN[sq as usize] {
+            mg += params.passed_mg[rank];
+            eg += params.passed_eg[rank];
+            passed = bb::set_bit(passed, sq);
+        }
+    }
+
+    (mg, eg, passed)
+}
+
+// endgame passed pawn terms that depend on the other pieces, so can't be cached with the structure
+fn evaluate_passers(params: &EvalParams, board: &Board, passed: u64, white: bool) -> i32 {
+    if passed == 0 {
+        return 0;
+    }
+
+    // look at the side's passers as white
+    let p = &board.placement;
+    let (own, theirs, occupied) = if white {
+        (p.white, p.black, p.white | p.black)
+    } else {
+        (p.black.swap_bytes(), p.white.swap_bytes(), (p.white | p.black).swap_bytes())
+    };
+    let passed = if white { passed } else { passed.swap_bytes() };
+    let kings = if white { p.kings } else { p.kings.swap_bytes() };
+    let pawns = if white { p.pawns } else { p.pawns.swap_bytes() };
+
+    let own_king = bb::to_sq(kings & own);
+    let their_king = bb::to_sq(kings & theirs);
+    let has_their_pieces = 0 != theirs & !pawns & !kings;
+    let is_their_turn = (board.turn == Color::WHITE) != white;
+
+    let mut eg = 0;
+    for sq in BitIterator::from(passed) {
+        let (rank, file) = bb::to_rank_file(sq);
+        let weight = rank - 2;
+        if weight <= 0 {
+            continue;
+        }
+
+        // the enemy king wants to be in front of the pawn, and its own king close behind
+        let stop = sq + 8;
+        eg += (bb::distance(their_king, stop) * params.their_king_distance - bb::distance(own_king, stop) * params.own_king_distance) * weight;
+
+        // a blocked passer is worth much less
+        if bb::has_bit(occupied, stop) {
+            eg -= params.passed_eg[rank as usize] / 2;
+        }
+
+        // rule of the square: the king can't reach the promotion square in time
+        let promotion = 56 + file;
+        let pawn_distance = 7 - rank;
+        let king_distance = bb::distance(their_king, promotion) - if is_their_turn { 1 } else { 0 };
+        if !has_their_pieces && 0 == occupied & FRONT_SPAN[sq as usize] && pawn_distance < king_distance {
+            eg += params.unstoppable;
+        }
+    }
+
+    eg
+}
+
+// squares ahead of each square on its own file and, given a width of one, the neighbouring files
+fn init_spans(width: i32) -> [u64; 64] {
+    let mut spans = [0; 64];
+    for (sq, span) in spans.iter_mut().enumerate() {
+        let (rank, file) = bb::to_rank_file(sq as i32);
+        for r in (rank + 1)..8 {
+            for f in (file - width)..=(file + width) {
+                if (0..8).contains(&f) {
+                    *span |= bb::to_bit(r, f);
+                }
+            }
+        }
+    }
+
+    spans
+}
+
+// squares on the neighbouring files from which a pawn could still come up to support the square
+fn init_support_spans() -> [u64; 64] {
+    let mut spans = [0; 64];
+    for (sq, span) in spans.iter_mut().enumerate() {
+        let (rank, file) = bb::to_rank_file(sq as i32);
+        for r in 0..=rank {
+            *span |= bb::adjacent_files(file) & (0xff << (r * 8));
+        }
+    }
+
+    spans
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use crate::engine::gen;
+use crate::engine::board::Board;
+use crate::engine::mov::Move;
+use crate::engine::piece::PieceType;
+use crate::engine::square::Square;
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+pub struct PgnGame {
+    pub headers: HashMap<String, String>,
+    pub moves: Vec<String>,
+    pub result: Option<String>,
+}
+
+impl PgnGame {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(|value| &value[..])
+    }
+
+    // game result from white's perspective: 1 for a win, 0.5 for a draw, 0 for a loss
+    pub fn white_score(&self) -> Option<f32> {
+        let result = self.header("Result").or_else(|| self.result.as_ref().map(|r| &r[..]));
+        match result {
+            Some("1-0") => Some(1.0),
+            Some("0-1") => Some(0.0),
+            Some("1/2-1/2") => Some(0.5),
+            _ => None,
+        }
+    }
+
+    // none when the game sets up a position that can't be parsed
+    pub fn start_position(&self) -> Option<Board> {
+        match self.header("FEN") {
+            Some(fen) => Board::parse(fen).ok(),
+            None => Some(Board::start_pos()),
+        }
+    }
+}
+
+pub struct PgnReader<R: BufRead> {
+    lines: std::io::Lines<R>,
+    pending_header: Option<String>,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            pending_header: None,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = PgnGame;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut headers = HashMap::new();
+        let mut movetext = String::new();
+
+        // the first header of this game may have been read while finishing the last one
+        if let Some(line) = self.pending_header.take() {
+            parse_header(&line, &mut headers);
+        }
+
+        while let Some(Ok(line)) = self.lines.next() {
+            let line = line.trim();
+            if line.starts_with('[') {
+
+                // a header after movetext begins the next game
+                if !movetext.trim().is_empty() {
+                    self.pending_header = Some(line.to_string());
+                    break;
+                }
+
+                parse_header(line, &mut headers);
+            } else if !line.starts_with('%') {
+                movetext.push_str(line);
+                movetext.push('\n');
+            }
+        }
+
+        if headers.is_empty() && movetext.trim().is_empty() {
+            return None;
+        }
+
+        let (moves, result) = parse_movetext(&movetext);
+        Some(PgnGame {
+            headers,
+            moves,
+            result,
+        })
+    }
+}
+
+fn parse_header(line: &str, headers: &mut HashMap<String, String>) {
+    let inner = line.trim_start_matches('[').trim_end_matches(']');
+    let mut parts = inner.splitn(2, ' ');
+    let name = parts.next().unwrap_or("");
+    let value = parts.next().unwrap_or("").trim().trim_matches('"');
+
+    if !name.is_empty() {
+        headers.insert(name.to_string(), value.to_string());
+    }
+}
+
+fn parse_movetext(movetext: &str) -> (Vec<String>, Option<String>) {
+    let mut moves = Vec::new();
+    let mut result = None;
+
+    let mut token = String::new();
+    let mut chars = movetext.chars();
+    let mut variation_depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+
+            // comments and variations are skipped entirely
+            '{' => {
+                for c in chars.by_ref() {
+                    if c == '}' { break; }
+                }
+            }
+            ';' => {
+                for c in chars.by_ref() {
+                    if c == '\n' { break; }
+                }
+            }
+            '(' => variation_depth += 1,
+            ')' => variation_depth -= 1,
+            _ if variation_depth > 0 => {}
+            _ if c.is_whitespace() => {
+                push_token(&mut token, &mut moves, &mut result);
+            }
+            _ => token.push(c),
+        }
+    }
+    push_token(&mut token, &mut moves, &mut result);
+
+    (moves, result)
+}
+
+fn push_token(token: &mut String, moves: &mut Vec<String>, result: &mut Option<String>) {
+    if RESULTS.contains(&&token[..]) {
+        *result = Some(token.clone());
+    } else if !token.starts_with('$') {
+
+        // strip move numbers, including those without a following space, i.e. "1.e4"
+        let san = match token.rfind('.') {
+            Some(i) => &token[(i + 1)..],
+            None => &token[..],
+        };
+        if !san.is_empty() {
+            moves.push(san.to_string());
+        }
+    }
+
+    token.clear();
+}
+
+pub fn parse_san(board: &Board, san: &str) -> Option<Move> {
+    let san = san.trim_end_matches(|c| "+#!?".contains(c));
+    let moves = gen::gen_moves(board);
+
+    // castling moves the king two files
+    if ["O-O", "0-0", "O-O-O", "0-0-0"].contains(&san) {
+        let file = if san.len() == 3 { 6 } else { 2 };
+        return moves.into_iter().find(|m| {
+            let is_king = Some(&PieceType::KING) == board.piece_type_at(m.from.idx as i32);
+            let (from_rank, from_file) = (m.from.idx / 8, m.from.idx % 8);
+            is_king && from_file == 4 && m.to.idx == from_rank * 8 + file
+        });
+    }
+
+    // split off the promotion, i.e. "e8=Q" or "e8Q"
+    let mut san = san.to_string();
+    let mut promotion = None;
+    if let Some(c) = san.chars().last() {
+        if "NBRQ".contains(c) && san.len() > 2 {
+            promotion = PieceType::parse(&c.to_string());
+            san.pop();
+            if san.ends_with('=') {
+                san.pop();
+            }
+        }
+    }
+
+    // the destination square is always the last two characters
+    if san.len() < 2 {
+        return None;
+    }
+    let to = Square::parse(&san[(san.len() - 2)..])?;
+    let prefix = &san[..(san.len() - 2)];
+
+    // pieces are named in upper case; pawns have no name at all
+    let mut chars = prefix.chars().peekable();
+    let piece_type = match chars.peek() {
+        Some(c) if c.is_ascii_uppercase() => {
+            let piece_type = PieceType::parse(&c.to_string())?;
+            chars.next();
+            piece_type
+        }
+        _ => &PieceType::PAWN,
+    };
+
+    // anything left besides the capture marker disambiguates the origin square
+    let mut from_file = None;
+    let mut from_rank = None;
+    for c in chars.filter(|c| *c != 'x') {
+        match c {
+            'a'..='h' => from_file = Some(c as u8 - b'a'),
+            '1'..='8' => from_rank = Some(c as u8 - b'1'),
+            _ => return None,
+        }
+    }
+
+    moves.into_iter().find(|m| {
+        m.to == to
+            && m.promotion == promotion
+            && Some(piece_type) == board.piece_type_at(m.from.idx as i32)
+            && from_file.is_none_or(|f| f == m.from.idx % 8)
+            && from_rank.is_none_or(|r| r == m.from.idx / 8)
+    })
+}
@@ -0,0 +1,156 @@
+use crate::engine::{gen, see};
+use crate::engine::board::Board;
+use crate::engine::history::History;
+use crate::engine::mov::Move;
+
+#[derive(Copy, Clone, PartialEq)]
+enum Stage {
+    TtMove,
+    GenCaptures,
+    GoodCaptures,
+    Killers,
+    GenQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+// yields moves in stages, best first, so that a cutoff skips generating the rest
+pub struct MovePicker {
+    stage: Stage,
+    tt_move: Option<Move>,
+    killers: [Option<Move>; 3],
+    previous: Option<Move>,
+    moves: Vec<(Move, i32)>,
+    bad_captures: Vec<(Move, i32)>,
+    index: usize,
+}
+
+impl MovePicker {
+    pub fn new(tt_move: Option<Move>, killers: [Option<Move>; 2], countermove: Option<Move>, previous: Option<Move>) -> Self {
+        Self {
+            stage: Stage::TtMove,
+            tt_move,
+            killers: [killers[0], killers[1], countermove],
+            previous,
+            moves: Vec::new(),
+            bad_captures: Vec::new(),
+            index: 0,
+        }
+    }
+
+    pub fn next(&mut self, position: &Board, history: &History) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::TtMove => {
+                    self.stage = Stage::GenCaptures;
+                    if let Some(mov) = self.tt_move {
+                        if gen::is_legal(position, &mov) {
+                            return Some(mov);
+                        }
+
+                        // never return a move that isn't legal here, even if it comes up again
+                        self.tt_move = None;
+                    }
+                }
+
+                Stage::GenCaptures => {
+                    self.moves = gen::gen_captures(position).into_iter()
+                        .filter(|m| Some(*m) != self.tt_move)
+                        .map(|m| (m, see::mvv_lva(position, &m)))
+                        .collect();
+                    self.index = 0;
+                    self.stage = Stage::GoodCaptures;
+                }
+
+                Stage::GoodCaptures => {
+                    match self.select() {
+
+                        // captures losing material in the exchange wait until quiet moves are tried
+                        Some((mov, score)) if !see::see_ge(position, &mov, 0) => self.bad_captures.push((mov, score)),
+                        Some((mov, _)) => return Some(mov),
+                        None => {
+                            self.index = 0;
+                            self.stage = Stage::Killers;
+                        }
+                    }
+                }
+
+                Stage::Killers => {
+                    if self.index >= self.killers.len() {
+                        self.stage = Stage::GenQuiets;
+                        continue;
+                    }
+
+                    let killer = self.killers[self.index];
+                    self.index += 1;
+                    if let Some(mov) = killer {
+                        if self.is_fresh_killer(&mov) && gen::is_quiet(position, &mov) && gen::is_legal(position, &mov) {
+                            return Some(mov);
+                        }
+
+                        // forget a killer that can't be played, so the quiet stage won't skip it
+                        self.killers[self.index - 1] = None;
+                    }
+                }
+
+                Stage::GenQuiets => {
+                    let previous = self.previous;
+                    self.moves = gen::gen_quiets(position).into_iter()
+                        .filter(|m| Some(*m) != self.tt_move && !self.killers.contains(&Some(*m)))
+                        .map(|m| (m, history.score(position, previous, &m)))
+                        .collect();
+                    self.index = 0;
+                    self.stage = Stage::Quiets;
+                }
+
+                Stage::Quiets => {
+                    match self.select() {
+                        Some((mov, _)) => return Some(mov),
+                        None => {
+                            self.moves = std::mem::take(&mut self.bad_captures);
+                            self.index = 0;
+                            self.stage = Stage::BadCaptures;
+                        }
+                    }
+                }
+
+                Stage::BadCaptures => {
+                    match self.select() {
+                        Some((mov, _)) => return Some(mov),
+                        None => self.stage = Stage::Done,
+                    }
+                }
+
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    // selection sort, one move at a time, since most nodes never need the whole list
+    fn select(&mut self) -> Option<(Move, i32)> {
+        if self.index >= self.moves.len() {
+            return None;
+        }
+
+        let mut best = self.index;
+        for i in (self.index + 1)..self.moves.len() {
+            if self.moves[i].1 > self.moves[best].1 {
+                best = i;
+            }
+        }
+
+        self.moves.swap(self.index, best);
+        self.index += 1;
+
+        Some(self.moves[self.index - 1])
+    }
+
+    // a killer may repeat the tt move, a capture already returned or put off until last, or another
+    // killer already tried; the captures are still in the list until quiet moves are generated
+    fn is_fresh_killer(&self, mov: &Move) -> bool {
+        Some(*mov) != self.tt_move
+            && !self.moves.iter().any(|(m, _)| m == mov)
+            && !self.killers[..(self.index - 1)].contains(&Some(*mov))
+    }
+}
@@ -0,0 +1,112 @@
+use crate::engine::bb::BitIterator;
+use crate::engine::board::Placement;
+use crate::engine::params::EvalParams;
+use crate::engine::piece::PieceType;
+
+// game phase by remaining material; all minor and major pieces on the board is the middlegame
+pub const MAX_PHASE: i32 = 24;
+
+// material and placement from white's perspective, kept up to date as moves are made
+#[derive(Copy, Clone, Default, PartialEq)]
+pub struct Psqt {
+    pub mg: i32,
+    pub eg: i32,
+    pub phase: i32,
+}
+
+impl Psqt {
+    pub fn of(params: &EvalParams, placement: &Placement) -> Self {
+        let by_type = [
+            placement.pawns,
+            placement.knights,
+            placement.bishops,
+            placement.rooks,
+            placement.queens,
+            placement.kings,
+        ];
+
+        let mut psqt = Self::default();
+        for (pieces, piece_type) in by_type.iter().zip(PieceType::PIECE_TYPES.iter()) {
+            for sq in BitIterator::from(pieces & placement.white) {
+                psqt.add(params, piece_type, true, sq);
+            }
+            for sq in BitIterator::from(pieces & placement.black) {
+                psqt.add(params, piece_type, false, sq);
+            }
+        }
+
+        psqt
+    }
+
+    pub fn add(&mut self, params: &EvalParams, piece_type: &PieceType, white: bool, sq: i32) {
+        let (mg, eg) = value(params, piece_type, white, sq);
+        self.mg += mg;
+        self.eg += eg;
+        self.phase += params.phase[index(piece_type)];
+    }
+
+    pub fn remove(&mut self, params: &EvalParams, piece_type: &PieceType, white: bool, sq: i32) {
+        let (mg, eg) = value(params, piece_type, white, sq);
+        self.mg -= mg;
+        self.eg -= eg;
+        self.phase -= params.phase[index(piece_type)];
+    }
+
+    // the tables are symmetric, so swapping colors only changes the sign
+    pub fn mirror(&self) -> Self {
+        Self {
+            mg: -self.mg,
+            eg: -self.eg,
+            phase: self.phase,
+        }
+    }
+
+    // blend middlegame and endgame scores by how much material is left; promotions can push the
+    // phase beyond the maximum
+    pub fn taper(&self, mg: i32, eg: i32) -> i32 {
+        let phase = self.phase.min(MAX_PHASE);
+        (mg * phase + eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+// one side's material and placement apart, each as (middlegame, endgame) and positive for the side
+pub fn trace(params: &EvalParams, placement: &Placement, white: bool) -> ((i32, i32), (i32, i32)) {
+    let own = if white { placement.white } else { placement.black };
+    let by_type = [
+        placement.pawns,
+        placement.knights,
+        placement.bishops,
+        placement.rooks,
+        placement.queens,
+        placement.kings,
+    ];
+
+    let mut material = (0, 0);
+    let mut squares = (0, 0);
+    for (i, pieces) in by_type.iter().enumerate() {
+        for sq in BitIterator::from(pieces & own) {
+            let table_sq = if white { sq ^ 56 } else { sq } as usize;
+            material.0 += params.mg_value[i];
+            material.1 += params.eg_value[i];
+            squares.0 += params.mg_table[i][table_sq];
+            squares.1 += params.eg_table[i][table_sq];
+        }
+    }
+
+    (material, squares)
+}
+
+fn value(params: &EvalParams, piece_type: &PieceType, white: bool, sq: i32) -> (i32, i32) {
+    let i = index(piece_type);
+
+    // tables start from a8, so white's squares are flipped and black's are already mirrored
+    let table_sq = if white { sq ^ 56 } else { sq } as usize;
+    let mg = params.mg_value[i] + params.mg_table[i][table_sq];
+    let eg = params.eg_value[i] + params.eg_table[i][table_sq];
+
+    if white { (mg, eg) } else { (-mg, -eg) }
+}
+
+fn index(piece_type: &PieceType) -> usize {
+    PieceType::PIECE_TYPES.iter().position(|p| *p == piece_type).unwrap()
+}
@@ -1,37 +1,171 @@
-use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
 
-use crate::engine::{EngineState, eval, gen, GoParams, Transposition};
-use crate::engine::board::{Board, CastleRights, Color, Placement};
+use crate::engine::{EngineState, eval, gen, GoParams, Score, SearchInfo, see};
+use crate::engine::board::{Board, Color};
+use crate::engine::history::History;
+use crate::engine::picker::MovePicker;
+use crate::engine::time::TimeManager;
 use crate::engine::mov::Move;
-use crate::engine::piece::PieceType;
+use crate::engine::tt::{Bound, Transposition, TranspositionTable};
 use std::sync::mpsc::Sender;
+use std::thread;
 
 
-const DEPTH: i32 = 4;
+// depth searched when go is given no limits, and the deepest any search will go
+const DEFAULT_DEPTH: i32 = 4;
+const MAX_DEPTH: i32 = 64;
 const Q_DEPTH: i32 = 10;
-const Q_CHECK_DEPTH: i32 = 6;
+const Q_CHECK_DEPTH: i32 = 2;
+
+// the most a position could be worth beyond the material captured, for delta pruning
+const DELTA_MARGIN: i32 = 200;
 
 // min/max values that won't overflow on negation
 const MIN_EVAL: i32 = -std::i32::MAX;
 const MAX_EVAL: i32 = -MIN_EVAL;
 
-pub fn search(state: Arc<Mutex<EngineState>>, p: GoParams, thread_index: usize, tx_stats: Sender<SearchStats>) {
+// mate is scored by distance from the root; anything beyond the bound is a forced mate
+const MATE_EVAL: i32 = 30000;
+const MAX_PLY: i32 = 256;
+pub const MATE_BOUND: i32 = MATE_EVAL - MAX_PLY;
+
+// aspiration windows start narrow around the last eval and fall back to a full search
+const ASPIRATION_MIN_DEPTH: i32 = 3;
+const ASPIRATION_WINDOW: i32 = 50;
+const ASPIRATION_MAX_WINDOW: i32 = 1000;
+
+// null move pruning is verified when the side to move has this few pieces, where zugzwang is likely
+const NULL_MIN_DEPTH: i32 = 3;
+const NULL_VERIFY_PIECES: u32 = 2;
+
+// Lazy-SMP: helper threads skip some iterations, each in its own pattern, so they spread out
+// over more depths instead of all searching the same one
+const SKIP_SIZE: [i32; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [i32; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+// how often, in nodes, a search checks whether it should stop
+const STOP_CHECK_NODES: u64 = 1024;
+
+// margins and limits for the pruning and reduction heuristics
+pub struct SearchParams {
+    pub rfp_max_depth: i32,
+    pub rfp_margin: i32,
+    pub razor_max_depth: i32,
+    pub razor_margin: i32,
+    pub futility_max_depth: i32,
+    pub futility_margin: i32,
+    pub lmr_min_depth: i32,
+    pub lmr_min_moves: usize,
+    pub lmr_base: f32,
+    pub lmr_divisor: f32,
+    pub singular_min_depth: i32,
+    pub singular_margin: i32,
+}
+
+impl SearchParams {
+    pub fn new() -> Self {
+        Self {
+            rfp_max_depth: 6,
+            rfp_margin: 100,
+            razor_max_depth: 2,
+            razor_margin: 300,
+            futility_max_depth: 2,
+            futility_margin: 150,
+            lmr_min_depth: 3,
+            lmr_min_moves: 3,
+            lmr_base: 0.75,
+            lmr_divisor: 2.25,
+            singular_min_depth: 6,
+            singular_margin: 2,
+        }
+    }
+
+    // reduce more the deeper the search and the later the move, always leaving at least one ply
+    fn reduction(&self, depth: i32, move_index: usize) -> i32 {
+        if depth < self.lmr_min_depth || move_index < self.lmr_min_moves {
+            return 0;
+        }
+
+        let r = self.lmr_base + (depth as f32).ln() * (move_index as f32).ln() / self.lmr_divisor;
+        (r as i32).clamp(0, depth - 2)
+    }
+}
+
+pub fn search(state: Arc<Mutex<EngineState>>, p: GoParams, thread_index: usize, stop: Arc<AtomicBool>, tx_result: Sender<SearchResult>) {
     let root_position = state.lock().unwrap().position.clone();
+    let time = TimeManager::new(&p, root_position.turn);
 
     let mut position = root_position;
-    for mov in p.search_moves {
-        position.push(mov);
+    for mov in p.search_moves.iter() {
+        position.push(*mov);
     }
 
+    // search to the requested depth, or as deep as time allows
+    let depth_limit = if p.depth > 0 {
+        p.depth.min(MAX_DEPTH)
+    } else if p.infinite || time.is_limited() {
+        MAX_DEPTH
+    } else {
+        DEFAULT_DEPTH
+    };
+
     let table = state.lock().unwrap().table.clone();
-    let mut searcher = NegamaxAb::new(table, thread_index);
+    let mut searcher = NegamaxAb::new(table, thread_index, depth_limit, Arc::clone(&stop));
+
+    // only the main thread reports progress and keeps track of time
+    if thread_index == 0 {
+        searcher.info_fn = Some(state.lock().unwrap().callbacks.info_fn);
+        searcher.time = Some(time);
+    }
     searcher.search(&position);
 
-    if let Err(_) = tx_stats.send(searcher.get_stats()) {
-        panic!("failed to send search statistics");
+    if thread_index == 0 {
+
+        // an infinite search doesn't end until the gui says so, even when there's nothing left to search
+        while p.infinite && !stop.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // the main thread is done, so the helpers are too
+        stop.store(true, Ordering::Relaxed);
+    }
+
+    if let Err(_) = tx_result.send(searcher.get_result()) {
+        panic!("failed to send search result");
+    }
+}
+
+// the outcome of one thread's deepest completed iteration
+pub struct SearchResult {
+    pub stats: SearchStats,
+    pub depth: i32,
+    pub eval: i32,
+    pub best_move: Option<Move>,
+}
+
+// threads vote for their best moves, with deeper and better scoring results counting for more
+pub fn vote(results: &[SearchResult]) -> Option<Move> {
+    let min_eval = results.iter().map(|r| r.eval as i64).min()?;
+    let weight = |r: &SearchResult| (r.eval as i64 - min_eval + 14) * r.depth as i64;
+
+    let mut votes: Vec<(Move, i64)> = Vec::new();
+    for r in results.iter() {
+        if let Some(mov) = r.best_move {
+            match votes.iter_mut().find(|(m, _)| *m == mov) {
+                Some((_, v)) => *v += weight(r),
+                None => votes.push((mov, weight(r))),
+            }
+        }
     }
+
+    // ties go to the deepest search
+    let votes_for = |r: &SearchResult| votes.iter().find(|(m, _)| Some(*m) == r.best_move).map_or(0, |(_, v)| *v);
+    results.iter()
+        .filter(|r| r.best_move.is_some())
+        .max_by_key(|r| (votes_for(r), r.depth))
+        .and_then(|r| r.best_move)
 }
 
 #[derive(Copy, Clone)]
@@ -74,15 +208,27 @@ impl SearchStats {
 
 trait Searcher {
     fn search(&mut self, position: &Board);
-    fn get_stats(&self) -> SearchStats;
+    fn get_result(&self) -> SearchResult;
 }
 
 struct NegamaxAb {
     stats: SearchStats,
-    table: Arc<Mutex<HashMap<u64, Transposition>>>,
+    table: Arc<TranspositionTable>,
     rng: rand::rngs::ThreadRng,
     ab_depth: i32,
+    depth_limit: i32,
     thread_index: usize,
+    info_fn: Option<fn(&SearchInfo)>,
+    time: Option<TimeManager>,
+    stop: Arc<AtomicBool>,
+    is_stopped: bool,
+    params: SearchParams,
+    history: History,
+    path: Vec<Option<Move>>,
+    excluded: Vec<Option<Move>>,
+    extensions: i32,
+    root_best_move: Option<Move>,
+    completed: Option<(i32, i32, Option<Move>)>,
 }
 
 impl Searcher for NegamaxAb {
@@ -95,422 +241,667 @@ impl Searcher for NegamaxAb {
         let start = Instant::now();
 
         // iterative deepening
-        for i in 1..=DEPTH {
+        let mut eval = 0;
+        for i in 1..=self.depth_limit {
+            if self.is_skipped(i) {
+                continue;
+            }
+
+            // don't start an iteration there isn't time to finish, though the first always runs
+            // so that there's a move to play
+            let is_time_left = self.time.as_ref().is_none_or(|t| t.can_start_iteration());
+            if i > 1 && (!is_time_left || self.stop.load(Ordering::Relaxed)) {
+                break;
+            }
+
             let mut position = position.clone();
-            let sign = if position.turn == Color::WHITE { 1 } else { -1 };
             self.ab_depth = i;
-            self.negamax(&mut position, i, MIN_EVAL, MAX_EVAL, sign);
+            eval = self.aspiration(&mut position, i, eval);
+
+            // an interrupted iteration can't be trusted
+            if self.is_stopped {
+                break;
+            }
+            self.completed = Some((i, eval, self.root_best_move));
+
+            // report each completed iteration
+            if let Some(info_fn) = self.info_fn {
+                self.stats.time_elapsed = start.elapsed();
+                info_fn(&SearchInfo {
+                    depth: i,
+                    seldepth: self.stats.max_depth,
+                    score: to_score(eval),
+                    nodes: self.stats.nodes_visited,
+                    nps: self.stats.nps(),
+                    time_ms: self.stats.time_elapsed.as_millis() as u64,
+                    hashfull: self.table.hashfull(),
+                    pv: self.principal_variation(&position, i),
+                });
+            }
         }
 
         self.stats.time_elapsed = start.elapsed();
     }
 
-    fn get_stats(&self) -> SearchStats {
-        self.stats
+    fn get_result(&self) -> SearchResult {
+        let (depth, eval, best_move) = self.completed.unwrap_or((0, 0, None));
+        SearchResult {
+            stats: self.stats,
+            depth,
+            eval,
+            best_move,
+        }
     }
 }
 
 impl NegamaxAb {
-    pub fn new(table: Arc<Mutex<HashMap<u64, Transposition>>>, thread_index: usize) -> Self {
+    pub fn new(table: Arc<TranspositionTable>, thread_index: usize, depth_limit: i32, stop: Arc<AtomicBool>) -> Self {
         Self {
             stats: SearchStats::new(),
             table,
             rng: rand::thread_rng(),
-            ab_depth: DEPTH,
+            ab_depth: depth_limit,
+            depth_limit,
             thread_index,
+            info_fn: None,
+            time: None,
+            stop,
+            is_stopped: false,
+            params: SearchParams::new(),
+            history: History::new(),
+            path: vec![None; MAX_PLY as usize],
+            excluded: vec![None; MAX_PLY as usize],
+            extensions: 0,
+            root_best_move: None,
+            completed: None,
         }
     }
 
-    fn negamax(&mut self, position: &mut Board, depth: i32, mut alpha: i32, beta: i32, sign: i32) -> i32 {
+    // Lazy-SMP: the main thread searches every depth, and the helpers some of them
+    fn is_skipped(&self, depth: i32) -> bool {
+        if self.thread_index == 0 {
+            return false;
+        }
+
+        let i = (self.thread_index - 1) % SKIP_SIZE.len();
+        (depth + SKIP_PHASE[i]) / SKIP_SIZE[i] % 2 != 0
+    }
+
+    // polled every so often; the main thread stops everyone once it runs out of time
+    fn should_stop(&mut self) -> bool {
+        if self.is_stopped {
+            return true;
+        }
+
+        // the first iteration always finishes, so there's a move to play
+        if self.ab_depth == 1 || !self.stats.nodes_visited.is_multiple_of(STOP_CHECK_NODES) {
+            return false;
+        }
+
+        if self.time.as_ref().is_some_and(|t| t.is_out_of_time(self.stats.nodes_visited)) {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+
+        self.is_stopped = self.stop.load(Ordering::Relaxed);
+        self.is_stopped
+    }
+
+    // search a narrow window around the previous iteration's eval, widening it on failure
+    fn aspiration(&mut self, position: &mut Board, depth: i32, previous_eval: i32) -> i32 {
+        if depth < ASPIRATION_MIN_DEPTH || previous_eval.abs() >= MATE_BOUND {
+            return self.negamax(position, depth, 0, MIN_EVAL, MAX_EVAL, true);
+        }
+
+        let mut delta = ASPIRATION_WINDOW;
+        let mut alpha = previous_eval - delta;
+        let mut beta = previous_eval + delta;
+        loop {
+            let eval = self.negamax(position, depth, 0, alpha, beta, true);
+            if self.is_stopped {
+                return eval;
+            }
+
+            if eval <= alpha {
+                alpha = alpha.saturating_sub(delta).max(MIN_EVAL);
+            } else if eval >= beta {
+                beta = beta.saturating_add(delta);
+            } else {
+                return eval;
+            }
+
+            // give up on the window once it's wide enough to be useless
+            delta *= 2;
+            if delta > ASPIRATION_MAX_WINDOW {
+                alpha = MIN_EVAL;
+                beta = MAX_EVAL;
+            }
+        }
+    }
+
+    // evaluations are relative to the side to move
+    fn negamax(&mut self, position: &mut Board, depth: i32, ply: i32, mut alpha: i32, mut beta: i32, allow_null: bool) -> i32 {
 
         // switch to quiescence search at max alpha-beta depth
         if depth == 0 {
-            return sign * self.quiesce(position, depth - 1, alpha, beta);
+            return self.quiesce(position, depth - 1, ply, alpha, beta);
         }
 
         // track search statistics
         self.stats.nodes_visited += 1;
         self.stats.max_depth = self.stats.max_depth.max(self.ab_depth - depth);
 
-        // find transposition
-        let transposition = self.read_transposition(&position);
-        if let Some(t) = transposition.as_ref() {
-            if let Some(eval_depth) = t.eval_depth {
+        // give up on the search; the caller will discard whatever this returns
+        if self.should_stop() {
+            return 0;
+        }
+
+        // draw by repetition or insufficient material, before the transposition table, whose results
+        // don't know how the position was reached; the root must still choose a move
+        if ply > 0 && (is_repetition(position, ply) || position.is_insufficient_material()) {
+            return 0;
+        }
 
-                // repeating the same position toward a draw?
-                if eval_depth > depth {
-                    return 0;
+        // fifty-move rule, which the root also leaves to the gui to claim
+        if ply > 0 && position.halfmove_clock >= 100 {
+            return fifty_move_eval(position, ply);
+        }
+
+        // mate distance pruning; no line from here can beat a mate already found closer to the root
+        if ply > 0 {
+            alpha = alpha.max(-MATE_EVAL + ply);
+            beta = beta.min(MATE_EVAL - ply - 1);
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+
+        // a singular extension search shares this position's hash, but not its result
+        let excluded = self.excluded[ply as usize];
+
+        // find transposition
+        let is_pv = alpha + 1 < beta;
+        let transposition = self.read_transposition(position, ply);
+        if let Some(t) = transposition.as_ref().filter(|_| excluded.is_none()) {
+
+            // already evaluated at least as deep? the stored bound may narrow the window or cut off,
+            // though not on the principal variation, where the line must be searched through
+            if t.depth >= depth && ply > 0 && !is_pv {
+                match t.bound {
+                    Bound::Exact => return t.eval,
+                    Bound::Lower => alpha = alpha.max(t.eval),
+                    Bound::Upper => beta = beta.min(t.eval),
                 }
 
-                // already evaluated at depth?
-                if eval_depth == depth {
-                    return t.eval.unwrap();
+                if alpha >= beta {
+                    return t.eval;
                 }
             }
         }
 
-        // fifty-move rule
-        if position.halfmove_clock >= 50 {
-            return 0;
+        // forward pruning is only safe away from the principal variation and out of check
+        let is_check = position.is_check();
+        let can_prune = !is_pv && !is_check && ply > 0 && excluded.is_none()
+            && alpha.abs() < MATE_BOUND && beta.abs() < MATE_BOUND;
+        let static_eval = if is_check { MIN_EVAL } else { evaluate_relative(position) };
+
+        // reverse futility pruning; far enough above beta that no reply will bring it back down
+        let sp = &self.params;
+        if can_prune && depth <= sp.rfp_max_depth && static_eval - sp.rfp_margin * depth >= beta {
+            return static_eval;
         }
 
-        // three-fold repetition
-        if is_three_fold(&position) {
-            return 0;
+        // razoring; so far below alpha that only tactics could help, so look at those alone
+        if can_prune && depth <= sp.razor_max_depth && static_eval + sp.razor_margin * depth < alpha {
+            let eval = self.quiesce(position, -1, ply, alpha, alpha + 1);
+            if eval <= alpha {
+                return eval;
+            }
         }
 
-        // generate moves to test for checkmate/stalemate
-        let mut moves = gen::gen_moves(&position);
+        // null move pruning; if passing still fails high, a real move almost certainly would too
+        let pieces = count_pieces(position);
+        if allow_null && !is_pv && !is_check && ply > 0 && depth >= NULL_MIN_DEPTH && pieces > 0 {
+            let reduction = 2 + depth / 4;
+            let null_depth = (depth - 1 - reduction).max(0);
 
-        // no available moves? the game is over
-        if moves.is_empty() {
-            let is_mate = position.is_check();
-            return if is_mate { MIN_EVAL + position.fullmove_number as i32 } else { 0 };
+            self.path[ply as usize] = None;
+            position.pass();
+            let eval = -self.negamax(position, null_depth, ply + 1, -beta, -beta + 1, false);
+            position.pop();
+
+            // with few pieces left, passing may be the only good option; verify with a real search
+            if eval >= beta {
+                let is_verified = pieces > NULL_VERIFY_PIECES
+                    || self.negamax(position, null_depth, ply, beta - 1, beta, false) >= beta;
+
+                // don't trust a mate found by passing
+                if is_verified {
+                    return if eval >= MATE_BOUND { beta } else { eval };
+                }
+            }
         }
 
-        // order moves to improve alpha-beta pruning
-        order_moves(&mut moves, position, transposition.as_ref());
+        // moves are generated lazily, in order, to improve alpha-beta pruning
+        let previous = if ply > 0 { self.path[ply as usize - 1] } else { None };
+
+        // Lazy-SMP: helper threads start the root from a different move than the main thread
+        let tt_move = transposition.as_ref().and_then(|t| t.best_move);
+        let tt_move = if ply == 0 && self.thread_index > 0 { None } else { tt_move };
+
+        let mut picker = MovePicker::new(
+            tt_move,
+            self.history.killers(ply),
+            self.history.countermove(previous),
+            previous,
+        );
+
+        // singular extension; when every alternative falls well short of the tt move, it's forced
+        let is_singular = match transposition.as_ref() {
+            Some(t) if ply > 0 && excluded.is_none() && tt_move.is_some() => {
+                let sp = &self.params;
+                if depth >= sp.singular_min_depth && t.depth >= depth - 3 && t.bound != Bound::Upper && t.eval.abs() < MATE_BOUND {
+                    let singular_beta = t.eval - sp.singular_margin * depth;
+
+                    self.excluded[ply as usize] = tt_move;
+                    let eval = self.negamax(position, (depth - 1) / 2, ply, singular_beta - 1, singular_beta, false);
+                    self.excluded[ply as usize] = None;
+
+                    eval < singular_beta
+                } else {
+                    false
+                }
+            }
+            _ => false,
+        };
 
-        // Lazy-SMP: reorder the moves according to the current thread
-        let swap_index = (moves.len() - 1).min(self.thread_index);
-        moves.swap(0, swap_index);
+        // one reply extension; a lone check evasion costs nothing to look at more deeply
+        let is_one_reply = is_check && gen::gen_moves(position).len() == 1;
 
         // choose the best variation
         let mut best_eval = MIN_EVAL;
         let mut best_move = None;
 
         // go deeper for each move
-        for mov in moves {
+        let mut window_alpha = alpha;
+        let is_futile = can_prune && depth <= self.params.futility_max_depth
+            && static_eval + self.params.futility_margin * depth <= alpha;
+        let mut quiets = Vec::new();
+        let mut i = 0;
+        while let Some(mov) = picker.next(position, &self.history) {
+            if Some(mov) == excluded {
+                continue;
+            }
+
+            i += 1;
+            let is_quiet = gen::is_quiet(position, &mov);
+            self.path[ply as usize] = Some(mov);
             position.push(mov);
-            let eval = -self.negamax(position, depth - 1, -beta, -alpha, -sign);
+
+            // futility pruning; quiet moves near the leaves can't raise a hopeless eval to alpha
+            let gives_check = position.is_check();
+            let is_late_quiet = i > 1 && is_quiet && !gives_check;
+            if is_futile && is_late_quiet {
+                position.pop();
+                continue;
+            }
+
+            // extend forcing moves, as long as this line hasn't been extended as far as it's deep
+            let is_forcing = gives_check || is_one_reply || (is_singular && Some(mov) == tt_move);
+            let extension = if is_forcing && self.extensions < self.ab_depth { 1 } else { 0 };
+            let depth = depth + extension;
+            self.extensions += extension;
+
+            // principal variation search: the first move is expected to be best, so the rest only
+            // need to prove they're no better with a null window, and are searched again if not
+            let eval = if i == 1 {
+                -self.negamax(position, depth - 1, ply + 1, -beta, -window_alpha, true)
+            } else {
+
+                // late move reductions; well-ordered quiet moves this late rarely turn out best
+                let reduction = if is_late_quiet && !is_check { self.params.reduction(depth, i - 1) } else { 0 };
+
+                let mut eval = -self.negamax(position, depth - 1 - reduction, ply + 1, -window_alpha - 1, -window_alpha, true);
+                if eval > window_alpha && reduction > 0 {
+                    eval = -self.negamax(position, depth - 1, ply + 1, -window_alpha - 1, -window_alpha, true);
+                }
+                if eval > window_alpha && eval < beta {
+                    -self.negamax(position, depth - 1, ply + 1, -beta, -window_alpha, true)
+                } else {
+                    eval
+                }
+            };
+
+            self.extensions -= extension;
             position.pop();
 
+            if is_quiet {
+                quiets.push(mov);
+            }
+
             if best_move.is_none() || eval > best_eval {
                 best_eval = eval;
                 best_move = Some(mov);
             }
 
-            // alpha-beta pruning
-            alpha = alpha.max(best_eval);
-            if alpha >= beta {
+            // alpha-beta pruning; remember quiet moves that refute this position
+            window_alpha = window_alpha.max(best_eval);
+            if window_alpha >= beta {
+                if is_quiet {
+                    self.history.update(position, ply, previous, &mov, &quiets, depth);
+                }
+
                 break;
             }
         }
 
+        // no available moves? the game is over, unless the only move was excluded
+        if i == 0 {
+            return if excluded.is_some() {
+                alpha
+            } else if is_check {
+                -MATE_EVAL + ply
+            } else {
+                0
+            };
+        }
+
+        // the result of a singular extension search is missing the best move
+        if excluded.is_some() {
+            return best_eval;
+        }
+
+        if ply == 0 {
+            self.root_best_move = best_move;
+        }
+
         // update transposition table with result
-        self.write_transposition(position, Transposition {
-            eval: Some(best_eval),
-            eval_depth: Some(depth),
-            q_eval: match &transposition {
-                Some(t) => t.q_eval,
-                None => None,
-            },
-            q_depth: match &transposition {
-                Some(t) => t.q_depth,
-                None => None,
-            },
+        self.write_transposition(position, ply, Transposition {
+            eval: best_eval,
+            depth,
+            bound: bound_of(best_eval, alpha, beta),
             best_move,
         });
 
         best_eval
     }
 
-    fn quiesce(&mut self, position: &mut Board, depth: i32, mut alpha: i32, beta: i32) -> i32 {
+    fn quiesce(&mut self, position: &mut Board, depth: i32, ply: i32, mut alpha: i32, beta: i32) -> i32 {
 
         // track search statistics
         self.stats.nodes_visited += 1;
         self.stats.max_depth = self.stats.max_depth.max(self.ab_depth - depth);
 
-        // find transposition
-        let (t_eval, t_depth) = self.read_transposition_q_eval(&position);
-        if let Some(q_eval) = t_eval {
-            if t_depth.unwrap() >= Q_DEPTH - depth.abs() {
-                return q_eval;
-            }
+        // draw by repetition or insufficient material; the root must still choose a move
+        if ply > 0 && (is_repetition(position, ply) || position.is_insufficient_material()) {
+            return 0;
         }
 
         // fifty-move rule
-        if position.halfmove_clock >= 50 {
-            return 0;
+        if position.halfmove_clock >= 100 {
+            return fifty_move_eval(position, ply);
         }
 
-        // three-fold repetition
-        if is_three_fold(&position) {
-            return 0;
+        // find transposition; any entry is at least as deep as a quiescence search
+        if let Some(t) = self.read_transposition(&position, ply) {
+            let is_cutoff = match t.bound {
+                Bound::Exact => true,
+                Bound::Lower => t.eval >= beta,
+                Bound::Upper => t.eval <= alpha,
+            };
+
+            if is_cutoff {
+                return t.eval;
+            }
         }
 
         // return static evaluation when maximum depth is reached
         if depth.abs() >= Q_DEPTH {
-            return eval::evaluate(position);
+            return evaluate_relative(position);
         }
 
-        // evaluate standing pat if not in check;
+        // in check, every evasion has to be searched and there's no standing pat
+        let alpha_orig = alpha;
         let is_check = position.is_check();
-        if !is_check {
-            let stand_pat = eval::evaluate(position);
+        let mut moves = if is_check {
+            let moves = gen::gen_moves(position);
+            if moves.is_empty() {
+                return -MATE_EVAL + ply;
+            }
+
+            moves
+        } else {
+
+            // otherwise the side to move may choose not to capture anything
+            let stand_pat = evaluate_relative(position);
             if stand_pat >= beta {
                 return beta;
             } else if alpha < stand_pat {
                 alpha = stand_pat;
             }
-        }
 
-        // generate moves
-        let mut moves = gen::gen_moves(position);
+            // delta pruning; skip captures that couldn't raise the eval to alpha even when free,
+            // and those that lose material in the exchange
+            let mut moves = gen::gen_captures(position);
+            moves.retain(|m| {
+                stand_pat + see::capture_value(position, m) + DELTA_MARGIN > alpha && see::see_ge(position, m, 0)
+            });
 
-        // no available moves? the game is over
-        if moves.is_empty() {
-            let is_mate = is_check;
-            return if is_mate { MIN_EVAL + position.fullmove_number as i32 } else { 0 };
-        }
+            // quiet checks only right at the horizon, where they may still hide a mate
+            if depth.abs() < Q_CHECK_DEPTH {
+                let mut checks = gen::gen_quiet_checks(position);
+                checks.retain(|m| see::see_ge(position, m, 0));
+                moves.append(&mut checks);
+            }
 
-        // include checks/checking moves at this depth?
-        let include_checks = depth.abs() < Q_CHECK_DEPTH;
+            moves
+        };
 
-        // remove quiet moves if not in check
-        if !(is_check && include_checks) {
-            moves.retain(|m| is_loud(position, m, include_checks));
-        }
+        // try the most valuable captures first; exchanges were only needed to prune
+        moves.sort_by_cached_key(|m| -see::mvv_lva(position, m));
 
+        let mut best_move = None;
         for mov in moves {
             position.push(mov);
-            let score = -self.quiesce(position, depth - 1, -beta, -alpha);
+            let score = -self.quiesce(position, depth - 1, ply + 1, -beta, -alpha);
             position.pop();
 
             if score >= beta {
+                self.write_transposition(position, ply, Transposition {
+                    eval: beta,
+                    depth: 0,
+                    bound: Bound::Lower,
+                    best_move: Some(mov),
+                });
+
                 return beta;
             } else if score > alpha {
                 alpha = score;
+                best_move = Some(mov);
             }
         }
 
         // update transposition table with result
-        self.write_transposition_q_eval(position, alpha, Q_DEPTH - depth.abs());
+        self.write_transposition(position, ply, Transposition {
+            eval: alpha,
+            depth: 0,
+            bound: if alpha > alpha_orig { Bound::Exact } else { Bound::Upper },
+            best_move,
+        });
 
         alpha
     }
 
-    fn read_transposition(&mut self, position: &Board) -> Option<Transposition> {
-        let transposition = self.table.lock().unwrap().get(&position.hash).cloned();
+    // follow best moves through the transposition table, stopping at anything illegal
+    fn principal_variation(&self, position: &Board, depth: i32) -> Vec<Move> {
+        let mut position = position.clone();
+        let mut pv = Vec::new();
+        while (pv.len() as i32) < depth {
+            let mov = match self.table.probe(position.hash).and_then(|t| t.best_move) {
+                Some(mov) => mov,
+                None => break,
+            };
+
+            if !gen::gen_moves(&position).contains(&mov) {
+                break;
+            }
 
-        if transposition.is_some() {
-            self.stats.tt_hits += 1;
+            position.push(mov);
+            pv.push(mov);
         }
 
-        transposition
+        pv
     }
 
-    fn write_transposition(&mut self, position: &Board, t: Transposition) {
-        let mut table = self.table.lock().unwrap();
+    fn read_transposition(&mut self, position: &Board, ply: i32) -> Option<Transposition> {
+        let mut transposition = self.table.probe(position.hash);
 
-        // do not overwrite a more valuable record; this can happen in parallel searches
-        if let Some(entry) = table.get(&position.hash) {
-            if t.eval_depth <= entry.eval_depth {
-                self.stats.tt_waste += 1;
-                return;
-            }
+        if let Some(t) = transposition.as_mut() {
+            self.stats.tt_hits += 1;
+            t.eval = eval_from_tt(t.eval, ply);
         }
 
-        table.insert(position.hash, t);
+        transposition
     }
 
-    fn read_transposition_q_eval(&mut self, position: &Board) -> (Option<i32>, Option<i32>) {
-        let transposition = self.table.lock().unwrap().get(&position.hash).cloned();
+    fn write_transposition(&mut self, position: &Board, ply: i32, mut t: Transposition) {
 
-        if let Some(t) = transposition {
-            if t.q_eval.is_some() {
-                self.stats.tt_hits += 1;
-            }
-            (t.q_eval, t.q_depth)
-        } else {
-            (None, None)
+        // results from an interrupted search are meaningless
+        if self.is_stopped {
+            return;
         }
-    }
 
-    fn write_transposition_q_eval(&mut self, position: &Board, q_eval: i32, q_depth: i32) {
-        let mut table = self.table.lock().unwrap();
+        t.eval = eval_to_tt(t.eval, ply);
 
-        if let Some(entry) = table.get_mut(&position.hash) {
-            if entry.q_eval.is_some() {
-                self.stats.tt_waste += 1;
-            } else {
-                entry.q_eval = Some(q_eval);
-                entry.q_depth = Some(q_depth);
-            }
-        } else {
-            table.insert(position.hash, Transposition {
-                eval: None,
-                eval_depth: None,
-                q_eval: Some(q_eval),
-                q_depth: Some(q_depth),
-                best_move: None
-            });
+        // a more valuable record may be kept instead; this can happen in parallel searches
+        if !self.table.store(position.hash, &t) {
+            self.stats.tt_waste += 1;
         }
     }
 }
 
-fn is_three_fold(position: &Board) -> bool {
-    if position.halfmove_clock > 4 {
-        let hash = position.hash;
-
-        let mut n = 0;
-        let mut previous = position.previous.as_ref();
-        while previous.is_some() {
-            let current = previous.unwrap();
-
-            // find a repetition
-            if hash == current.hash {
-                n += 1;
-                if n >= 2 {
-                    return true;
-                }
-            }
+fn evaluate_relative(position: &Board) -> i32 {
+    match position.turn {
+        Color::WHITE => eval::evaluate(position),
+        Color::BLACK => -eval::evaluate(position),
+    }
+}
 
-            // look deeper unless halfmove_clock indicates irreversible move
-            previous = if current.halfmove_clock > 0 {
-                current.previous.as_ref()
-            } else {
-                None
-            }
-        }
+fn bound_of(eval: i32, alpha: i32, beta: i32) -> Bound {
+    if eval <= alpha {
+        Bound::Upper
+    } else if eval >= beta {
+        Bound::Lower
+    } else {
+        Bound::Exact
     }
+}
+
+// pieces besides pawns and the king belonging to the side to move
+fn count_pieces(position: &Board) -> u32 {
+    let p = &position.placement;
+    let own = match position.turn {
+        Color::WHITE => p.white,
+        Color::BLACK => p.black,
+    };
 
-    false
+    ((p.knights | p.bishops | p.rooks | p.queens) & own).count_ones()
 }
 
-fn order_moves(moves: &mut Vec<Move>, board: &Board, transposition: Option<&Transposition>) {
-    let mut pv = None;
-    if let Some(t) = transposition {
-        pv = t.best_move;
+// convert a side-relative eval into centipawns or moves to mate
+fn to_score(eval: i32) -> Score {
+    if eval >= MATE_BOUND {
+        Score::Mate((MATE_EVAL - eval + 1) / 2)
+    } else if eval <= -MATE_BOUND {
+        Score::Mate(-(MATE_EVAL + eval) / 2)
+    } else {
+        Score::Cp(eval)
     }
+}
 
-    let mut orders = Vec::with_capacity(moves.len());
-    while !moves.is_empty() {
-        let mov = moves.pop().unwrap();
-        let order = if pv.is_some() && mov == pv.unwrap() {
-            MAX_EVAL
-        } else {
-            eval::evaluate_exchange(board, &mov)
-        };
-
-        orders.push((mov, order));
+// mate scores are stored relative to the position rather than to the root of the search
+fn eval_to_tt(eval: i32, ply: i32) -> i32 {
+    if eval >= MATE_BOUND {
+        eval + ply
+    } else if eval <= -MATE_BOUND {
+        eval - ply
+    } else {
+        eval
     }
+}
 
-    orders.sort_by_key(|o| o.1);
-    while !orders.is_empty() {
-        moves.push(orders.pop().unwrap().0);
+fn eval_from_tt(eval: i32, ply: i32) -> i32 {
+    if eval >= MATE_BOUND {
+        eval - ply
+    } else if eval <= -MATE_BOUND {
+        eval + ply
+    } else {
+        eval
     }
 }
 
-fn is_loud(position: &Board, mov: &Move, include_checks: bool) -> bool {
+// a position repeated anywhere since the root is scored as a draw, since the side that could
+// avoid it would have done so; positions from the game history need to repeat twice more
+fn is_repetition(position: &Board, ply: i32) -> bool {
+    position.repetitions().enumerate().any(|(n, distance)| distance <= ply || n >= 1)
+}
 
-    // queen promotions
-    if let Some(&PieceType::QUEEN) = mov.promotion {
-        return true;
+// the fifty-move rule doesn't apply when the last move delivered checkmate
+fn fifty_move_eval(position: &Board, ply: i32) -> i32 {
+    if position.is_check() && gen::gen_moves(position).is_empty() {
+        -MATE_EVAL + ply
+    } else {
+        0
     }
+}
 
-    // captures
-    let mut capture_sq = mov.to.idx as i32;
-    if let Some(ep_target) = position.en_passant_target {
-        let moving_pawn = 0 != position.placement.pawns & (1 << mov.from.idx);
-        if moving_pawn && ep_target.idx == mov.to.idx {
-            match position.turn {
-                Color::WHITE => capture_sq -= 8,
-                Color::BLACK => capture_sq += 8,
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn after(board: &Board, moves: &[&str]) -> Board {
+        let mut board = board.clone();
+        for mov in moves {
+            board.push(Move::parse(mov).unwrap());
         }
-    }
-    if 0 != (position.placement.white | position.placement.black) & (1 << capture_sq) {
-        return true;
-    }
 
-    // checks; fake a new position after a non-capture move and test it
-    if !include_checks {
-        return false;
+        board
     }
 
-    let from_bit = 1 << mov.from.idx;
-    let pawn_bit = position.placement.pawns & from_bit;
-    let knight_bit = position.placement.knights & from_bit;
-    let bishop_bit = position.placement.bishops & from_bit;
-    let rook_bit = position.placement.rooks & from_bit;
-    let queen_bit = position.placement.queens & from_bit;
-    let king_bit = position.placement.kings & from_bit;
-    let white_bit = position.placement.white & from_bit;
-    let black_bit = position.placement.black & from_bit;
-
-    let mut pawns = position.placement.pawns & !from_bit;
-    let mut knights = position.placement.knights & !from_bit;
-    let mut bishops = position.placement.bishops & !from_bit;
-    let mut rooks = position.placement.rooks & !from_bit;
-    let mut queens = position.placement.queens & !from_bit;
-    let mut kings = position.placement.kings & !from_bit;
-    let mut white = position.placement.white & !from_bit;
-    let mut black = position.placement.black & !from_bit;
-
-    let mut diff_sq = mov.to.idx as i32 - mov.from.idx as i32;
-    if diff_sq < 0 { diff_sq += 64; }
-    let diff_sq = diff_sq as u32;
-
-    match mov.promotion {
-        Some(&PieceType::KNIGHT) => knights |= 1 << mov.to.idx,
-        Some(&PieceType::BISHOP) => bishops |= 1 << mov.to.idx,
-        Some(&PieceType::ROOK) => rooks |= 1 << mov.to.idx,
-        Some(&PieceType::QUEEN) => queens |= 1 << mov.to.idx,
-        _ => {
-            pawns |= pawn_bit.rotate_left(diff_sq);
-            knights |= knight_bit.rotate_left(diff_sq);
-            bishops |= bishop_bit.rotate_left(diff_sq);
-            rooks |= rook_bit.rotate_left(diff_sq);
-            queens |= queen_bit.rotate_left(diff_sq);
-            kings |= king_bit.rotate_left(diff_sq);
-        }
-    }
-    white |= white_bit.rotate_left(diff_sq);
-    black |= black_bit.rotate_left(diff_sq);
-
-    if let Some(castling_rook) = mov.get_castling_rook() {
-        let rook_bit = 1 << castling_rook.from.idx;
-        let white_bit = position.placement.white & rook_bit;
-        let black_bit = position.placement.black & rook_bit;
-        let mut diff_sq = castling_rook.to.idx as i32 - castling_rook.from.idx as i32;
-        if diff_sq < 0 { diff_sq += 64; };
-        let diff_sq = diff_sq as u32;
-        rooks &= !rook_bit;
-        white |= white_bit.rotate_left(diff_sq);
-        black |= black_bit.rotate_right(diff_sq);
-    }
+    const SHUFFLE: [&str; 4] = ["g1f3", "g8f6", "f3g1", "f6g8"];
 
-    let mut spoof = Board {
-        placement: Placement {
-            pawns,
-            knights,
-            bishops,
-            rooks,
-            queens,
-            kings,
-            white,
-            black,
-        },
-        turn: position.turn,
-        castle_rights: CastleRights {
-            kingside_w: false,
-            queenside_w: false,
-            kingside_b: false,
-            queenside_b: false,
-        },
-        en_passant_target: None,
-        halfmove_clock: 0,
-        fullmove_number: 0,
-        previous: None,
-        hash: 0,
-    };
-    if spoof.turn == Color::BLACK {
-        spoof = spoof.mirror();
+    #[test]
+    fn repetition_within_search() {
+
+        // the start position comes back after four plies, all of them searched
+        let board = after(&Board::start_pos(), &SHUFFLE);
+        assert!(is_repetition(&board, 4));
+
+        // and not when only some of the knight moves were
+        let board = after(&Board::start_pos(), &SHUFFLE[..2]);
+        assert!(!is_repetition(&board, 2));
     }
-    if !0 != gen::get_check_restriction(&spoof) {
-        return true;
+
+    #[test]
+    fn repetition_through_game_history() {
+
+        // a position seen once before the root may still be played for a win
+        let board = after(&Board::start_pos(), &SHUFFLE);
+        assert!(!is_repetition(&board, 0));
+        assert!(!is_repetition(&board, 3));
+
+        // seen twice before, it's the third time
+        let board = after(&board, &SHUFFLE);
+        assert!(is_repetition(&board, 0));
     }
 
-    false
+    #[test]
+    fn repetition_after_irreversible_moves() {
+
+        // the shuffle before the pawn moves doesn't add to the count after them
+        let board = after(&Board::start_pos(), &SHUFFLE);
+        let board = after(&board, &["e2e3", "e7e6"]);
+        let board = after(&board, &SHUFFLE);
+        assert!(!is_repetition(&board, 0));
+        assert!(is_repetition(&board, 4));
+    }
 }
@@ -0,0 +1,219 @@
+use crate::engine::{bb, eval, params};
+use crate::engine::bb::BitIterator;
+use crate::engine::board::{Board, Color, Placement};
+use crate::engine::mov::Move;
+use crate::engine::params::EvalParams;
+use crate::engine::piece::PieceType;
+
+// high enough that capturing the king always ends an exchange
+const KING_VALUE: i32 = 20000;
+
+// static exchange evaluation: the material won or lost on the destination square if both sides
+// keep recapturing with their least valuable attacker, each stopping once it would lose more
+pub fn see(board: &Board, mov: &Move) -> i32 {
+    params::with(|params| exchange(params, board, mov))
+}
+
+fn exchange(params: &EvalParams, board: &Board, mov: &Move) -> i32 {
+
+    // castling never captures and never leaves anything en prise on the king's square
+    if mov.get_castling_rook().is_some() && board.piece_type_at(mov.from.idx as i32) == Some(&PieceType::KING) {
+        return 0;
+    }
+
+    let from = mov.from.idx as i32;
+    let to = mov.to.idx as i32;
+    let placement = &board.placement;
+    let mut occupied = placement.white | placement.black;
+
+    let moving = board.piece_type_at(from).expect("no piece to exchange");
+    let mut gain = [0; 32];
+    gain[0] = match board.piece_type_at(to) {
+        Some(captured) => value(params, captured),
+        None if is_en_passant(board, mov, moving) => {
+
+            // the captured pawn is not on the destination square
+            let captured_sq = if board.turn == Color::WHITE { to - 8 } else { to + 8 };
+            occupied = bb::clear_bit(occupied, captured_sq);
+            value(params, &PieceType::PAWN)
+        }
+        None => 0,
+    };
+
+    // a promotion gains the difference between the pieces
+    let mut on_square = value(params, moving);
+    if let Some(promotion) = mov.promotion {
+        gain[0] += value(params, promotion) - value(params, &PieceType::PAWN);
+        on_square = value(params, promotion);
+    }
+
+    occupied = bb::clear_bit(occupied, from);
+    let mut attackers = attackers_to(placement, to, occupied) & occupied;
+    let mut white = board.turn != Color::WHITE;
+
+    // gain[d] is the balance if the exchange goes on to capture d; the last one is speculative
+    let mut d = 0;
+    loop {
+        d += 1;
+        gain[d] = on_square - gain[d - 1];
+
+        // stopping once both choices lose would keep the sign, but not the value, of the exchange
+        if d == gain.len() - 1 {
+            break;
+        }
+
+        let own = if white { placement.white } else { placement.black };
+        let (sq, piece_type) = match least_valuable(placement, attackers & own) {
+            Some(attacker) => attacker,
+            None => break,
+        };
+
+        // a king can't recapture into an attack
+        occupied = bb::clear_bit(occupied, sq);
+        if *piece_type == PieceType::KING && 0 != attackers_to(placement, to, occupied) & occupied & !own {
+            break;
+        }
+
+        // removing the attacker may reveal a slider behind it
+        attackers |= attackers_to(placement, to, occupied);
+        attackers &= occupied;
+        on_square = value(params, piece_type);
+        white = !white;
+    }
+
+    while d > 1 {
+        d -= 1;
+        gain[d - 1] = -(-gain[d - 1]).max(gain[d]);
+    }
+
+    gain[0]
+}
+
+pub fn see_ge(board: &Board, mov: &Move, threshold: i32) -> bool {
+    see(board, mov) >= threshold
+}
+
+// material won by a capture, including a promotion, before anything recaptures
+pub fn capture_value(board: &Board, mov: &Move) -> i32 {
+    params::with(|params| {
+        let moving = board.piece_type_at(mov.from.idx as i32);
+        let captured = match board.piece_type_at(mov.to.idx as i32) {
+            Some(piece_type) => eval::piece_value(params, piece_type),
+            None if moving.is_some_and(|m| is_en_passant(board, mov, m)) => eval::piece_value(params, &PieceType::PAWN),
+            None => 0,
+        };
+
+        let promoted = mov.promotion.map_or(0, |p| eval::piece_value(params, p) - eval::piece_value(params, &PieceType::PAWN));
+        captured + promoted
+    })
+}
+
+// orders captures cheaply: most valuable victim first, then least valuable attacker
+pub fn mvv_lva(board: &Board, mov: &Move) -> i32 {
+    let attacker = board.piece_type_at(mov.from.idx as i32)
+        .and_then(|piece_type| PieceType::PIECE_TYPES.iter().position(|p| *p == piece_type))
+        .unwrap_or(0);
+
+    8 * capture_value(board, mov) - attacker as i32
+}
+
+fn attackers_to(placement: &Placement, sq: i32, occupied: u64) -> u64 {
+    let diagonal = placement.bishops | placement.queens;
+    let line = placement.rooks | placement.queens;
+
+    let mut attackers = 0;
+    attackers |= bb::pawn_attackers(sq, true) & placement.pawns & placement.white;
+    attackers |= bb::pawn_attackers(sq, false) & placement.pawns & placement.black;
+    attackers |= bb::KNIGHT_MOVES[sq as usize] & placement.knights;
+    attackers |= bb::KING_MOVES[sq as usize] & placement.kings;
+    attackers |= bb::bishop_attacks(sq, occupied) & diagonal;
+    attackers |= bb::rook_attacks(sq, occupied) & line;
+
+    attackers
+}
+
+fn least_valuable(placement: &Placement, attackers: u64) -> Option<(i32, &'static PieceType)> {
+    let by_value = [
+        (placement.pawns, &PieceType::PAWN),
+        (placement.knights, &PieceType::KNIGHT),
+        (placement.bishops, &PieceType::BISHOP),
+        (placement.rooks, &PieceType::ROOK),
+        (placement.queens, &PieceType::QUEEN),
+        (placement.kings, &PieceType::KING),
+    ];
+
+    for (pieces, piece_type) in by_value.iter() {
+        if let Some(sq) = BitIterator::from(attackers & pieces).next() {
+            return Some((sq, *piece_type));
+        }
+    }
+
+    None
+}
+
+fn is_en_passant(board: &Board, mov: &Move, moving: &PieceType) -> bool {
+    *moving == PieceType::PAWN && board.en_passant_target.is_some_and(|sq| sq.idx == mov.to.idx)
+}
+
+fn value(params: &EvalParams, piece_type: &PieceType) -> i32 {
+    if *piece_type == PieceType::KING {
+        KING_VALUE
+    } else {
+        eval::piece_value(params, piece_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn see_of(fen: &str, mov: &str) -> i32 {
+        see(&Board::new(fen), &Move::parse(mov).unwrap())
+    }
+
+    fn values() -> [i32; 5] {
+        EvalParams::default().piece_values
+    }
+
+    #[test]
+    fn free_captures() {
+        let [pawn, _, _, _, queen] = values();
+        assert_eq!(pawn, see_of("4k3/8/8/3p4/8/8/8/3RK3 w - - 0 1", "d1d5"));
+        assert_eq!(queen, see_of("4k3/8/8/3q4/4P3/8/8/4K3 w - - 0 1", "e4d5"));
+        assert_eq!(pawn, see_of("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"));
+    }
+
+    #[test]
+    fn pawn_takes_defended_knight() {
+        let [pawn, knight, _, _, _] = values();
+        assert_eq!(knight - pawn, see_of("4k3/8/4p3/3n4/4P3/8/8/4K3 w - - 0 1", "e4d5"));
+    }
+
+    #[test]
+    fn queen_takes_defended_pawn() {
+        let [pawn, _, _, _, queen] = values();
+        let fen = "4k3/8/4p3/3p4/8/8/3Q4/4K3 w - - 0 1";
+        assert_eq!(pawn - queen, see_of(fen, "d2d5"));
+        assert!(!see_ge(&Board::new(fen), &Move::parse("d2d5").unwrap(), 0));
+    }
+
+    #[test]
+    fn rook_battery_x_ray() {
+        let [pawn, _, _, rook, _] = values();
+
+        // alone, the rook is lost for the pawn
+        assert_eq!(pawn - rook, see_of("3rk3/8/8/3p4/8/8/3R4/4K3 w - - 0 1", "d2d5"));
+
+        // the rook behind it recaptures once the first is taken
+        assert_eq!(pawn, see_of("3rk3/8/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5"));
+    }
+
+    #[test]
+    fn king_cannot_recapture_into_attack() {
+
+        // the king takes the rook on e2, unless the bishop defends it
+        let [pawn, _, _, rook, _] = values();
+        assert_eq!(pawn - rook, see_of("4k3/4r3/8/8/8/8/4P3/4K3 b - - 0 1", "e7e2"));
+        assert_eq!(pawn, see_of("4k3/4r3/8/7b/8/8/4P3/4K3 b - - 0 1", "e7e2"));
+    }
+}
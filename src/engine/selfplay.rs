@@ -0,0 +1,196 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex, mpsc};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+
+use rand::Rng;
+
+use crate::engine::{Callbacks, EngineState, gen, GoParams, LogLevel, SearchInfo};
+use crate::engine::board::{Board, Color};
+use crate::engine::mov::Move;
+use crate::engine::search;
+use crate::engine::search::MATE_BOUND;
+use crate::engine::tt::TranspositionTable;
+
+pub struct SelfPlayParams {
+    pub games: usize,
+    pub depth: i32,
+    pub nodes: i32,
+    pub random_plies: usize,
+    pub max_plies: usize,
+    pub threads: usize,
+    pub hash_mb: usize,
+}
+
+#[derive(Default)]
+pub struct SelfPlayStats {
+    pub games: usize,
+    pub positions: usize,
+    pub white_wins: usize,
+    pub draws: usize,
+    pub black_wins: usize,
+}
+
+// one recorded position: its fen, the search score from white's perspective and the move played
+struct Sample {
+    fen: String,
+    eval: i32,
+    best_move: Move,
+}
+
+// plays games on every thread, writing lines of "<fen> | <score> | <best move> | <result>" with the
+// score and result from white's perspective, which the tune tool reads as they are
+pub fn play(params: &SelfPlayParams, output: &str) -> io::Result<SelfPlayStats> {
+    let mut out = BufWriter::new(File::create(output)?);
+    writeln!(out, "# fen | score | best move | result")?;
+
+    let next_game = AtomicUsize::new(0);
+    let shared = Mutex::new((out, SelfPlayStats::default()));
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..params.threads.max(1)).map(|_| {
+            scope.spawn(|| -> io::Result<()> {
+                let table = Arc::new(TranspositionTable::new(params.hash_mb));
+                while next_game.fetch_add(1, Ordering::Relaxed) < params.games {
+                    let (samples, result) = play_game(params, &table);
+
+                    // whole games are written at once so that they don't interleave
+                    let mut shared = shared.lock().unwrap();
+                    let (out, stats) = &mut *shared;
+                    for sample in samples.iter() {
+                        writeln!(out, "{} | {} | {} | {:.1}", sample.fen, sample.eval, sample.best_move.uci(), result)?;
+                    }
+
+                    stats.games += 1;
+                    stats.positions += samples.len();
+                    match result {
+                        r if r > 0.5 => stats.white_wins += 1,
+                        r if r < 0.5 => stats.black_wins += 1,
+                        _ => stats.draws += 1,
+                    }
+
+                    if stats.games.is_multiple_of(10) || stats.games == params.games {
+                        eprintln!("{}/{} games, {} positions", stats.games, params.games, stats.positions);
+                    }
+                }
+
+                Ok(())
+            })
+        }).collect();
+
+        workers.into_iter().try_for_each(|w| w.join().unwrap())
+    })?;
+
+    let (mut out, stats) = shared.into_inner().unwrap();
+    out.flush()?;
+    Ok(stats)
+}
+
+// the positions worth learning from, and the result from white's perspective
+fn play_game(params: &SelfPlayParams, table: &Arc<TranspositionTable>) -> (Vec<Sample>, f32) {
+    let mut board = random_opening(params.random_plies);
+    table.clear();
+
+    let mut samples = Vec::new();
+    for _ in 0..params.max_plies {
+        if let Some(result) = game_result(&board) {
+            return (samples, result);
+        }
+
+        let (eval, best_move) = match search_position(params, table, &board) {
+            (eval, Some(mov)) => (eval, mov),
+            (_, None) => break,
+        };
+
+        // the static evaluation can't be expected to see checks, captures or forced mates
+        let is_quiet = !board.is_check() && gen::is_quiet(&board, &best_move);
+        if is_quiet && eval.abs() < MATE_BOUND {
+            samples.push(Sample {
+                fen: board.fen(),
+                eval: if board.turn == Color::WHITE { eval } else { -eval },
+                best_move,
+            });
+        }
+
+        board.push(best_move);
+    }
+
+    // games that drag on are called drawn
+    (samples, game_result(&board).unwrap_or(0.5))
+}
+
+// a few random moves from the start, so that games don't all repeat each other
+fn random_opening(plies: usize) -> Board {
+    let mut rng = rand::thread_rng();
+    loop {
+        let mut board = Board::start_pos();
+        for _ in 0..plies {
+            let moves = gen::gen_moves(&board);
+            if moves.is_empty() {
+                break;
+            }
+            board.push(moves[rng.gen_range(0, moves.len())]);
+        }
+
+        if game_result(&board).is_none() {
+            return board;
+        }
+    }
+}
+
+// the side to move's eval and best move, from a single-threaded search
+fn search_position(params: &SelfPlayParams, table: &Arc<TranspositionTable>, board: &Board) -> (i32, Option<Move>) {
+    let state = Arc::new(Mutex::new(EngineState {
+        callbacks: Callbacks {
+            log_fn: ignore_log,
+            best_move_fn: ignore_best_move,
+            info_fn: ignore_info,
+        },
+        position: board.clone(),
+        table: Arc::clone(table),
+    }));
+
+    let go = GoParams {
+        search_moves: Vec::new(),
+        ponder: false,
+        wtime: 0,
+        btime: 0,
+        winc: 0,
+        binc: 0,
+        movestogo: 0,
+        depth: params.depth,
+        nodes: params.nodes,
+        mate: 0,
+        movetime: 0,
+        infinite: false,
+    };
+
+    let (tx_result, rx_result) = mpsc::channel();
+    table.new_search();
+    search::search(state, go, 0, Arc::new(AtomicBool::new(false)), tx_result);
+
+    let result = rx_result.recv().expect("no search result");
+    (result.eval, result.best_move)
+}
+
+// the result from white's perspective once the game is over
+fn game_result(board: &Board) -> Option<f32> {
+    if gen::gen_moves(board).is_empty() {
+        return Some(match (board.is_check(), board.turn) {
+            (true, Color::WHITE) => 0.0,
+            (true, Color::BLACK) => 1.0,
+            (false, _) => 0.5,
+        });
+    }
+
+    let is_draw = board.halfmove_clock >= 100 || board.is_insufficient_material() || board.repetitions().count() >= 2;
+    if is_draw { Some(0.5) } else { None }
+}
+
+fn ignore_log(_: LogLevel, _: &str) {}
+
+fn ignore_best_move(_: Option<&Move>) {}
+
+fn ignore_info(_: &SearchInfo) {}
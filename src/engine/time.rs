@@ -0,0 +1,72 @@
+use std::time::{Duration, Instant};
+
+use crate::engine::board::Color;
+use crate::engine::GoParams;
+
+// allowance for communication with the gui, so that the clock never runs out
+const MOVE_OVERHEAD_MS: i32 = 30;
+
+// assumed number of moves left when the gui doesn't say how many to make before the next control
+const DEFAULT_MOVES_TO_GO: i32 = 30;
+
+// decides how long the main thread searches; helpers are stopped when it finishes
+pub struct TimeManager {
+    start: Instant,
+    optimum: Option<Duration>,
+    maximum: Option<Duration>,
+    nodes: Option<u64>,
+}
+
+impl TimeManager {
+    pub fn new(p: &GoParams, turn: Color) -> Self {
+        let (time, inc) = match turn {
+            Color::WHITE => (p.wtime, p.winc),
+            Color::BLACK => (p.btime, p.binc),
+        };
+
+        let (optimum, maximum) = if p.infinite {
+            (None, None)
+        } else if p.movetime > 0 {
+            let movetime = (p.movetime - MOVE_OVERHEAD_MS).max(1);
+            (Some(movetime), Some(movetime))
+        } else if time > 0 {
+
+            // spread the remaining time over the moves to go, going over only when a search is unstable
+            let available = (time - MOVE_OVERHEAD_MS).max(1);
+            let moves_to_go = if p.movestogo > 0 { p.movestogo } else { DEFAULT_MOVES_TO_GO };
+            let optimum = (available / moves_to_go + inc * 3 / 4).min(available);
+            let maximum = (optimum * 4).min(available / 2).max(optimum);
+            (Some(optimum), Some(maximum))
+        } else {
+            (None, None)
+        };
+
+        Self {
+            start: Instant::now(),
+            optimum: optimum.map(millis),
+            maximum: maximum.map(millis),
+            nodes: if p.nodes > 0 { Some(p.nodes as u64) } else { None },
+        }
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.maximum.is_some() || self.nodes.is_some()
+    }
+
+    // another iteration usually takes longer than all the previous ones together
+    pub fn can_start_iteration(&self) -> bool {
+        match self.optimum {
+            Some(optimum) => self.start.elapsed() < optimum / 2,
+            None => true,
+        }
+    }
+
+    pub fn is_out_of_time(&self, nodes_visited: u64) -> bool {
+        self.maximum.is_some_and(|maximum| self.start.elapsed() >= maximum)
+            || self.nodes.is_some_and(|nodes| nodes_visited >= nodes)
+    }
+}
+
+fn millis(ms: i32) -> Duration {
+    Duration::from_millis(ms as u64)
+}
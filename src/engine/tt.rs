@@ -0,0 +1,290 @@
+use std::mem;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::engine::mov::Move;
+
+const BUCKET_SIZE: usize = 4;
+const HASHFULL_SAMPLE: usize = 1000;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Bound {
+    Exact = 1,
+    Lower = 2,
+    Upper = 3,
+}
+
+#[derive(Clone)]
+pub struct Transposition {
+    pub eval: i32,
+    pub depth: i32,
+    pub bound: Bound,
+    pub best_move: Option<Move>,
+}
+
+// entries are read and written without locking; the key is stored XOR-ed with the data so that
+// an entry torn apart by concurrent writes fails verification instead of returning bad data
+#[derive(Default)]
+struct Entry {
+    check: AtomicU64,
+    data: AtomicU64,
+}
+
+#[derive(Default)]
+struct Bucket {
+    entries: [Entry; BUCKET_SIZE],
+}
+
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    age: AtomicU8,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let len = (size_mb * 1024 * 1024 / mem::size_of::<Bucket>()).max(1);
+        let mut buckets = Vec::with_capacity(len);
+        buckets.resize_with(len, Bucket::default);
+
+        Self {
+            buckets,
+            age: AtomicU8::new(0),
+        }
+    }
+
+    pub fn clear(&self) {
+        for bucket in self.buckets.iter() {
+            for entry in bucket.entries.iter() {
+                entry.save(0, 0);
+            }
+        }
+
+        self.age.store(0, Ordering::Relaxed);
+    }
+
+    // entries from previous searches become the first to be replaced
+    pub fn new_search(&self) {
+        self.age.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<Transposition> {
+        for entry in self.bucket(hash).entries.iter() {
+            let (key, data) = entry.load();
+            if key == hash && is_used(data) {
+                return Some(decode(data));
+            }
+        }
+
+        None
+    }
+
+    // returns false when a more valuable record of the same position was kept instead
+    pub fn store(&self, hash: u64, t: &Transposition) -> bool {
+        let age = self.age.load(Ordering::Relaxed);
+        let bucket = self.bucket(hash);
+
+        // update the slot already holding this position, if any, unless it's deeper and current;
+        // otherwise quiescence results would wipe out full-depth ones
+        for entry in bucket.entries.iter() {
+            let (key, data) = entry.load();
+            if key == hash && is_used(data) {
+                let old = decode(data);
+                if old.depth > t.depth && entry_age(data) == age {
+                    return false;
+                }
+
+                // keep the old move rather than forget it
+                let t = Transposition {
+                    best_move: t.best_move.or(old.best_move),
+                    ..t.clone()
+                };
+
+                entry.save(hash, encode(&t, age));
+                return true;
+            }
+        }
+
+        // otherwise take an empty slot or replace the least valuable entry, preferring shallow
+        // entries left over from older searches
+        let victim = bucket.entries.iter().min_by_key(|entry| {
+            let (_, data) = entry.load();
+            if is_used(data) { replacement_value(data, age) } else { i32::MIN }
+        });
+
+        victim.unwrap().save(hash, encode(t, age));
+        true
+    }
+
+    // permille of sampled entries that were written during the current search
+    pub fn hashfull(&self) -> usize {
+        let age = self.age.load(Ordering::Relaxed);
+        let sample = (HASHFULL_SAMPLE / BUCKET_SIZE).min(self.buckets.len());
+
+        let mut used = 0;
+        for bucket in self.buckets[..sample].iter() {
+            for entry in bucket.entries.iter() {
+                let (_, data) = entry.load();
+                if is_used(data) && entry_age(data) == age {
+                    used += 1;
+                }
+            }
+        }
+
+        1000 * used / (sample * BUCKET_SIZE)
+    }
+
+    fn bucket(&self, hash: u64) -> &Bucket {
+        let idx = (hash as u128 * self.buckets.len() as u128) >> 64;
+        &self.buckets[idx as usize]
+    }
+}
+
+impl Entry {
+    fn load(&self) -> (u64, u64) {
+        let check = self.check.load(Ordering::Relaxed);
+        let data = self.data.load(Ordering::Relaxed);
+
+        (check ^ data, data)
+    }
+
+    fn save(&self, key: u64, data: u64) {
+        self.check.store(key ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+}
+
+// best move (16) | eval (16) | depth (8) | age (8) | bound (2)
+fn encode(t: &Transposition, age: u8) -> u64 {
+    let best_move = t.best_move.map_or(0, |m| m.pack()) as u64;
+    let eval = t.eval.clamp(i16::MIN as i32 + 1, i16::MAX as i32) as i16 as u16 as u64;
+    let depth = t.depth.clamp(0, 255) as u64;
+
+    best_move | (eval << 16) | (depth << 32) | ((age as u64) << 40) | ((t.bound as u64) << 48)
+}
+
+fn decode(data: u64) -> Transposition {
+    let bound = match (data >> 48) & 0x3 {
+        1 => Bound::Exact,
+        2 => Bound::Lower,
+        _ => Bound::Upper,
+    };
+
+    // a packed move of zero would be a1a1, which can't happen
+    let best_move = (data & 0xffff) as u16;
+
+    Transposition {
+        eval: ((data >> 16) & 0xffff) as u16 as i16 as i32,
+        depth: ((data >> 32) & 0xff) as i32,
+        bound,
+        best_move: if best_move != 0 { Some(Move::unpack(best_move)) } else { None },
+    }
+}
+
+// every stored entry has a bound, so an empty slot is all zeros
+fn is_used(data: u64) -> bool {
+    0 != (data >> 48) & 0x3
+}
+
+fn entry_age(data: u64) -> u8 {
+    ((data >> 40) & 0xff) as u8
+}
+
+fn replacement_value(data: u64, age: u8) -> i32 {
+    let depth = ((data >> 32) & 0xff) as i32;
+    let staleness = age.wrapping_sub(entry_age(data)) as i32;
+
+    depth - 8 * staleness
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a table of one bucket, so that every key competes for the same entries
+    fn table() -> TranspositionTable {
+        TranspositionTable::new(0)
+    }
+
+    fn entry(eval: i32, depth: i32, bound: Bound, best_move: Option<&str>) -> Transposition {
+        Transposition {
+            eval,
+            depth,
+            bound,
+            best_move: best_move.map(|m| Move::parse(m).unwrap()),
+        }
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let moves = [Some("e2e4"), Some("a7a8q"), Some("h1a8"), None];
+        let bounds = [Bound::Exact, Bound::Lower, Bound::Upper];
+        for (i, (mov, bound)) in moves.iter().zip(bounds.iter().cycle()).enumerate() {
+            let t = entry(-29990 + 9999 * i as i32, 17 * i as i32, *bound, *mov);
+            let decoded = decode(encode(&t, 200));
+
+            assert_eq!(t.eval, decoded.eval);
+            assert_eq!(t.depth, decoded.depth);
+            assert!(t.bound == decoded.bound);
+            assert_eq!(t.best_move.map(|m| m.uci()), decoded.best_move.map(|m| m.uci()));
+            assert_eq!(200, entry_age(encode(&t, 200)));
+        }
+    }
+
+    #[test]
+    fn probe_misses_other_keys() {
+        let table = table();
+        table.store(1, &entry(10, 3, Bound::Exact, None));
+
+        assert_eq!(10, table.probe(1).unwrap().eval);
+        assert!(table.probe(2).is_none());
+
+        table.clear();
+        assert!(table.probe(1).is_none());
+    }
+
+    #[test]
+    fn deeper_current_entry_is_kept() {
+        let table = table();
+        assert!(table.store(1, &entry(10, 8, Bound::Lower, Some("e2e4"))));
+
+        // not even an exact quiescence result replaces it
+        assert!(!table.store(1, &entry(20, 0, Bound::Exact, None)));
+        assert_eq!(8, table.probe(1).unwrap().depth);
+
+        // but one at least as deep does, keeping the old move when it has none
+        assert!(table.store(1, &entry(30, 8, Bound::Exact, None)));
+        let t = table.probe(1).unwrap();
+        assert_eq!(30, t.eval);
+        assert_eq!("e2e4", t.best_move.unwrap().uci());
+    }
+
+    #[test]
+    fn older_entry_is_replaced() {
+        let table = table();
+        table.store(1, &entry(10, 8, Bound::Exact, None));
+        table.new_search();
+
+        assert!(table.store(1, &entry(20, 2, Bound::Upper, None)));
+        assert_eq!(2, table.probe(1).unwrap().depth);
+    }
+
+    #[test]
+    fn least_valuable_entry_is_evicted() {
+        let table = table();
+        for key in 1..=BUCKET_SIZE as u64 {
+            table.store(key, &entry(0, 4 + key as i32, Bound::Exact, None));
+        }
+
+        // the bucket is full, so the shallowest entry goes
+        table.store(100, &entry(0, 1, Bound::Exact, None));
+        assert!(table.probe(1).is_none());
+        assert!(table.probe(100).is_some());
+
+        // entries from older searches go before deeper ones from this search
+        table.new_search();
+        table.store(101, &entry(0, 1, Bound::Exact, None));
+        table.store(102, &entry(0, 1, Bound::Exact, None));
+        assert!(table.probe(101).is_some());
+        assert!(table.probe(102).is_some());
+        assert!(table.probe(100).is_none());
+    }
+}
@@ -0,0 +1,285 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::thread;
+
+use crate::engine::{eval, gen, params, see};
+use crate::engine::board::{Board, Color};
+use crate::engine::mov::Move;
+use crate::engine::params::EvalParams;
+use crate::engine::pgn;
+use crate::engine::pgn::PgnReader;
+use crate::engine::psqt::Psqt;
+
+// quiet positions are those the static evaluation is meant for, so captures are resolved first
+const MAX_QUIESCE_PLY: usize = 16;
+
+pub struct TuneParams {
+    pub iterations: usize,
+    pub step: i32,
+    pub skip_plies: usize,
+    pub max_positions: usize,
+}
+
+pub struct Tuner {
+    params: TuneParams,
+    threads: usize,
+
+    // the quiet end of each position, with its game result from white's perspective
+    positions: Vec<(Board, f64)>,
+    k: f64,
+}
+
+impl Tuner {
+    pub fn new(params: TuneParams) -> Self {
+        Self {
+            params,
+            threads: num_cpus::get(),
+            positions: Vec::new(),
+            k: 1.0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    // pgn files are replayed move by move; anything else is read as epd lines ending in a result
+    pub fn add_file(&mut self, path: &str) -> io::Result<()> {
+        let reader = BufReader::new(File::open(path)?);
+        if path.ends_with(".pgn") {
+            for game in PgnReader::new(reader) {
+                self.add_game(&game);
+            }
+
+            return Ok(());
+        }
+
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match parse_epd(line) {
+                Some((board, result)) => self.add_position(board, result),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("bad epd line: {}", line))),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_game(&mut self, game: &pgn::PgnGame) {
+        let result = match game.white_score() {
+            Some(score) => score as f64,
+            None => return,
+        };
+
+        // the opening is mostly book moves, which say little about the evaluation
+        let mut board = match game.start_position() {
+            Some(board) => board,
+            None => return,
+        };
+        for (ply, san) in game.moves.iter().enumerate() {
+            let mov = match pgn::parse_san(&board, san) {
+                Some(mov) => mov,
+                None => return,
+            };
+            board.push(mov);
+
+            if ply >= self.params.skip_plies {
+                let mut position = board.clone();
+                position.previous = None;
+                self.add_position(position, result);
+            }
+        }
+    }
+
+    fn add_position(&mut self, mut board: Board, result: f64) {
+        if self.positions.len() >= self.params.max_positions || board.is_check() {
+            return;
+        }
+
+        // keep the position at the end of the principal variation, where nothing is hanging
+        let mut pv = Vec::new();
+        quiesce(&mut board, -i32::MAX, i32::MAX, 0, &mut pv);
+
+        let mut leaf = board.clone();
+        for mov in pv {
+            leaf.push(mov);
+        }
+        leaf.previous = None;
+
+        self.positions.push((leaf, result));
+    }
+
+    // tunes every weight, starting from the current parameters, and writes them out after each pass
+    pub fn run(&mut self, output: &str) -> io::Result<EvalParams> {
+        let mut best = params::with(|params| params.clone());
+        let mut values = best.values();
+
+        self.k = self.fit_k(&best);
+        let mut best_loss = self.loss(&best);
+        eprintln!("{} positions, k {:.3}, loss {:.6}", self.len(), self.k, best_loss);
+
+        for iteration in 0..self.params.iterations {
+            let mut improved = 0;
+            for i in 0..values.len() {
+
+                // try a step either way, keeping whichever helps
+                for step in [self.params.step, -self.params.step].iter() {
+                    values[i] += step;
+                    let mut candidate = best.clone();
+                    candidate.set_values(&values);
+
+                    let loss = self.loss(&candidate);
+                    if loss < best_loss {
+                        best = candidate;
+                        best_loss = loss;
+                        improved += 1;
+                        break;
+                    }
+                    values[i] -= step;
+                }
+            }
+
+            eprintln!("iteration {}: {} weights changed, loss {:.6}", iteration + 1, improved, best_loss);
+            best.save(output)?;
+            if improved == 0 {
+                break;
+            }
+        }
+
+        // the engine goes on with the tuned weights
+        params::set(best.clone());
+        Ok(best)
+    }
+
+    // the scale that turns centipawns into an expected score best for these positions
+    fn fit_k(&self, params: &EvalParams) -> f64 {
+        let mut k = 1.0;
+        let mut step = 0.5;
+        let mut best_loss = f64::MAX;
+
+        params::set(params.clone());
+        let evals = self.evaluate_all();
+        while step > 0.001 {
+            for candidate in [k - step, k, k + step].iter().filter(|k| **k > 0.0) {
+                let loss = self.loss_of(&evals, *candidate);
+                if loss < best_loss {
+                    best_loss = loss;
+                    k = *candidate;
+                }
+            }
+            step /= 2.0;
+        }
+
+        k
+    }
+
+    fn loss(&self, params: &EvalParams) -> f64 {
+        params::set(params.clone());
+        let evals = self.evaluate_all();
+        self.loss_of(&evals, self.k)
+    }
+
+    // mean squared error between the results and the expected scores of the evaluations
+    fn loss_of(&self, evals: &[i32], k: f64) -> f64 {
+        let total: f64 = self.positions.iter().zip(evals.iter()).map(|((_, result), eval)| {
+            let expected = 1.0 / (1.0 + 10f64.powf(-k * *eval as f64 / 400.0));
+            (result - expected).powi(2)
+        }).sum();
+
+        total / self.positions.len().max(1) as f64
+    }
+
+    // evaluates every position with the current parameters, split across threads
+    fn evaluate_all(&self) -> Vec<i32> {
+        let chunk_size = self.positions.len().div_ceil(self.threads).max(1);
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = self.positions.chunks(chunk_size).map(|chunk| {
+                scope.spawn(move || {
+                    chunk.iter().map(|(board, _)| {
+
+                        // piece-square scores were kept up to date with other weights
+                        let mut board = board.clone();
+                        board.psqt = params::with(|params| Psqt::of(params, &board.placement));
+                        eval::evaluate(&board)
+                    }).collect::<Vec<i32>>()
+                })
+            }).collect();
+
+            handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+        })
+    }
+}
+
+// "<fen> <result>", with the result as 1-0, 0-1 or 1/2-1/2, or a score in brackets, optionally quoted
+// after an epd opcode; move counters may be left out
+fn parse_epd(line: &str) -> Option<(Board, f64)> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    if tokens.len() < 5 {
+        return None;
+    }
+
+    let has_counters = tokens.len() >= 7 && tokens[4].parse::<u16>().is_ok() && tokens[5].parse::<u16>().is_ok();
+    let (fen, rest) = if has_counters {
+        (tokens[..6].join(" "), &tokens[6..])
+    } else {
+        (format!("{} 0 1", tokens[..4].join(" ")), &tokens[4..])
+    };
+
+    let result = rest.iter().find_map(|token| {
+        match token.trim_matches(|c| c == '"' || c == ';' || c == '[' || c == ']') {
+            "1-0" | "1.0" => Some(1.0),
+            "0-1" | "0.0" => Some(0.0),
+            "1/2-1/2" | "0.5" => Some(0.5),
+            _ => None,
+        }
+    })?;
+
+    // a bad fen skips the line rather than stop the run
+    let board = Board::parse(&fen).ok()?;
+    Some((board, result))
+}
+
+// a plain search of captures and promotions, recording the line it ends on
+fn quiesce(position: &mut Board, mut alpha: i32, beta: i32, ply: usize, pv: &mut Vec<Move>) -> i32 {
+    let stand_pat = match position.turn {
+        Color::WHITE => eval::evaluate(position),
+        Color::BLACK => -eval::evaluate(position),
+    };
+
+    if stand_pat >= beta || ply >= MAX_QUIESCE_PLY {
+        return stand_pat;
+    }
+    alpha = alpha.max(stand_pat);
+
+    // losing exchanges are pruned, and the rest tried in the same order as the engine's quiescence
+    let mut moves = gen::gen_captures(position);
+    moves.retain(|m| see::see_ge(position, m, 0));
+    moves.sort_by_cached_key(|m| -see::mvv_lva(position, m));
+
+    for mov in moves {
+        let mut line = Vec::new();
+        position.push(mov);
+        let score = -quiesce(position, -beta, -alpha, ply + 1, &mut line);
+        position.pop();
+
+        if score > alpha {
+            alpha = score;
+            pv.clear();
+            pv.push(mov);
+            pv.append(&mut line);
+
+            if score >= beta {
+                break;
+            }
+        }
+    }
+
+    alpha
+}
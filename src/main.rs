@@ -1,7 +1,7 @@
 #[macro_use]
 extern crate lazy_static;
 
-use std::{io, process};
+use std::{env, io, process};
 
 use colored::*;
 
@@ -9,11 +9,19 @@ use crate::protocol::*;
 
 mod protocol;
 mod engine;
+mod tools;
 
 
 fn main() {
     println!("{} {}", env!("CARGO_PKG_NAME").red().bold(), env!("CARGO_PKG_VERSION"));
 
+    // command line arguments run one of the offline tools instead of a protocol
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        tools::run(args);
+        return;
+    }
+
     // first command gives the protocol name
     let protocol_command: String = read_line();
     let mut protocol: Box<Protocol> = match protocol_command.as_ref() {
@@ -1,4 +1,3 @@
-use std::collections::HashMap;
 use std::iter::Peekable;
 use std::process;
 use std::slice::Iter;
@@ -18,6 +17,7 @@ impl Uci {
             engine: Engine::new(Callbacks {
                 log_fn: log::info,
                 best_move_fn: uci_out::bestmove,
+                info_fn: uci_out::info,
             }),
         }
     }
@@ -25,7 +25,9 @@ impl Uci {
     fn uci(&mut self) {
         uci_out::id_name(env!("CARGO_PKG_NAME"));
         uci_out::id_author(env!("CARGO_PKG_AUTHORS"));
-        uci_out::option(HashMap::new());
+        for option in self.engine.options() {
+            uci_out::option(&option);
+        }
         uci_out::uciok();
     }
 
@@ -41,9 +43,22 @@ impl Uci {
         uci_out::readyok();
     }
 
-    fn setoption(&self, _args: Vec<&str>) {
+    fn setoption(&mut self, args: Vec<&str>) {
+
+        // names and values may both contain spaces: "name <id> [value <x>]"
+        let name_index = args.iter().position(|arg| *arg == "name");
+        let name_index = name_index.map(|i| i + 1).unwrap_or(0);
+        let value_index = args.iter().position(|arg| *arg == "value");
+        let value_index = value_index.unwrap_or(args.len());
+
+        let name = args[name_index.min(value_index)..value_index].join(" ");
+        let value = if value_index < args.len() {
+            args[(value_index + 1)..].join(" ")
+        } else {
+            String::new()
+        };
 
-        // no options are supported; just return
+        self.engine.set_option(&name, &value);
     }
 
     fn register(&self, _args: Vec<&str>) {
@@ -61,8 +76,9 @@ impl Uci {
         let moves_index = args.iter().position(|arg| *arg == "moves");
         let moves_index = moves_index.unwrap_or(args.len());
 
-        // join "args" back into a FEN string
-        let fen = args[0..moves_index].join(" ");
+        // join "args" back into a FEN string, skipping the "fen" keyword
+        let fen_index = if args.first() == Some(&"fen") { 1 } else { 0 };
+        let fen = args[fen_index.min(moves_index)..moves_index].join(" ");
 
         // parse the moves, if any
         let mut moves = Vec::new();
@@ -134,6 +150,16 @@ impl Uci {
         self.engine.stop();
     }
 
+    // not part of the protocol, but useful to see why the engine likes a position
+    fn eval(&self) {
+        uci_out::eval_trace(&self.engine.eval_trace());
+    }
+
+    // also not part of the protocol; writes the current weights out to edit and load with EvalFile
+    fn saveeval(&self, args: Vec<&str>) {
+        self.engine.save_eval_params(&args.join(" "));
+    }
+
     fn ponderhit(&self) {
         unimplemented!();
     }
@@ -162,6 +188,8 @@ impl Protocol for Uci {
             "stop" => self.stop(),
             "ponderhit" => self.ponderhit(),
             "quit" => self.quit(),
+            "eval" => self.eval(),
+            "saveeval" => self.saveeval(args),
             _ => (),
         }
     }
@@ -215,7 +243,7 @@ mod log {
 
 
 mod uci_out {
-    use std::collections::HashMap;
+    use crate::engine::{EngineOption, EvalTrace, OptionType, Score, SearchInfo};
     use crate::engine::mov::Move;
 
     pub fn id_name(name: &str) {
@@ -234,16 +262,59 @@ mod uci_out {
         println!("readyok");
     }
 
-    pub fn bestmove(mov: &Move) {
-        println!("bestmove {}", mov.uci());
+    // a null move when there's no legal move to play
+    pub fn bestmove(mov: Option<&Move>) {
+        println!("bestmove {}", mov.map_or(String::from("0000"), |m| m.uci()));
+    }
+
+    pub fn info(info: &SearchInfo) {
+        let score = match info.score {
+            Score::Cp(cp) => format!("cp {}", cp),
+            Score::Mate(moves) => format!("mate {}", moves),
+        };
+        let pv: Vec<String> = info.pv.iter().map(|m| m.uci()).collect();
+
+        println!("info depth {} seldepth {} score {} nodes {} nps {} time {} hashfull {} pv {}",
+                 info.depth, info.seldepth, score, info.nodes, info.nps, info.time_ms, info.hashfull, pv.join(" "));
+    }
+
+    pub fn eval_trace(trace: &EvalTrace) {
+        println!("{:>12} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}", "term", "white", "", "black", "", "total", "");
+        println!("{:>12} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}", "", "mg", "eg", "mg", "eg", "mg", "eg");
+        for term in trace.terms.iter() {
+            let (white_mg, white_eg) = term.white;
+            let (black_mg, black_eg) = term.black;
+            println!("{:>12} | {:>6} {:>6} | {:>6} {:>6} | {:>6} {:>6}",
+                     term.name, white_mg, white_eg, black_mg, black_eg, white_mg - black_mg, white_eg - black_eg);
+        }
+
+        println!();
+        println!("phase: {}/{}", trace.phase, trace.max_phase);
+        println!("endgame scale: {}/{}", trace.scale, trace.max_scale);
+        if let Some(special) = trace.special {
+            println!("special case: {}", special);
+        }
+        if trace.network {
+            println!("network: active, in place of the terms above");
+        }
+        println!("eval (white): {}", trace.eval);
     }
 
     pub fn info_string(msg: &str) {
         println!("info string {}", msg)
     }
 
-    pub fn option(_options: HashMap<&str, &str>) {
-        // TODO implement option
-        println!("option");
+    pub fn option(option: &EngineOption) {
+        match option.option_type {
+            OptionType::Check(default) => {
+                println!("option name {} type check default {}", option.name, default);
+            }
+            OptionType::Spin(default, min, max) => {
+                println!("option name {} type spin default {} min {} max {}", option.name, default, min, max);
+            }
+            OptionType::String(default) => {
+                println!("option name {} type string default {}", option.name, default);
+            }
+        }
     }
 }
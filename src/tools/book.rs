@@ -0,0 +1,41 @@
+use std::fs::File;
+use std::io::BufReader;
+
+use crate::engine::book::{BookBuilder, BuildParams};
+use crate::engine::pgn::PgnReader;
+use crate::tools::{exit_with, Options};
+
+const USAGE: &str = "usage: book <output> <pgn>... [--plies N] [--min-games N] [--min-elo N] [--format polyglot|native]";
+
+pub fn run(options: Options) {
+    if options.positional.len() < 2 {
+        exit_with(USAGE);
+    }
+
+    let output = &options.positional[0];
+    let mut builder = BookBuilder::new(BuildParams {
+        max_ply: options.get("plies", 20),
+        min_games: options.get("min-games", 1),
+        min_elo: options.get("min-elo", 0),
+    });
+
+    for path in options.positional[1..].iter() {
+        let file = File::open(path).unwrap_or_else(|e| exit_with(&format!("failed to open {}: {}", path, e)));
+        for game in PgnReader::new(BufReader::new(file)) {
+            builder.add_game(&game);
+        }
+    }
+
+    // default to polyglot for .bin files since that's what the engine expects of them
+    let format = options.get_str("format").unwrap_or(if output.ends_with(".bin") { "polyglot" } else { "native" });
+    let written = match format {
+        "polyglot" => builder.write_polyglot(output),
+        "native" => builder.write_native(output),
+        _ => exit_with(USAGE),
+    };
+
+    match written {
+        Ok(n) => eprintln!("{} games added, {} skipped, {} entries written to {}", builder.games_added, builder.games_skipped, n, output),
+        Err(e) => exit_with(&format!("failed to write {}: {}", output, e)),
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::process;
+use std::str::FromStr;
+
+mod book;
+mod selfplay;
+mod tune;
+
+pub fn run(args: Vec<String>) {
+    let command = &args[0];
+    let options = Options::parse(&args[1..]);
+
+    match command.as_ref() {
+        "book" => book::run(options),
+        "selfplay" => selfplay::run(options),
+        "tune" => tune::run(options),
+        _ => exit_with(&format!("unknown command: {}", command)),
+    }
+}
+
+pub fn exit_with(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    process::exit(1);
+}
+
+// command line arguments: positional values plus "--name value" pairs
+pub struct Options {
+    pub positional: Vec<String>,
+    named: HashMap<String, String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Self {
+        let mut positional = Vec::new();
+        let mut named = HashMap::new();
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if let Some(name) = arg.strip_prefix("--") {
+                let value = iter.next().unwrap_or_else(|| exit_with(&format!("missing value for --{}", name)));
+                named.insert(name.to_string(), value.to_string());
+            } else {
+                positional.push(arg.to_string());
+            }
+        }
+
+        Self {
+            positional,
+            named,
+        }
+    }
+
+    pub fn get<T: FromStr>(&self, name: &str, default: T) -> T {
+        match self.named.get(name) {
+            Some(value) => value.parse().unwrap_or_else(|_| exit_with(&format!("invalid value for --{}: {}", name, value))),
+            None => default,
+        }
+    }
+
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.named.get(name).map(|value| &value[..])
+    }
+}
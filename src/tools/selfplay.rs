@@ -0,0 +1,30 @@
+use crate::engine::selfplay;
+use crate::engine::selfplay::SelfPlayParams;
+use crate::tools::{exit_with, Options};
+
+const USAGE: &str = "usage: selfplay <output> [--games N] [--depth N] [--nodes N] [--random-plies N] [--max-plies N] [--threads N] [--hash MB]";
+
+pub fn run(options: Options) {
+    if options.positional.len() != 1 {
+        exit_with(USAGE);
+    }
+
+    // a node limit alone searches as deep as it allows
+    let nodes = options.get("nodes", 0);
+    let params = SelfPlayParams {
+        games: options.get("games", 100),
+        depth: options.get("depth", if nodes > 0 { 0 } else { 6 }),
+        nodes,
+        random_plies: options.get("random-plies", 8),
+        max_plies: options.get("max-plies", 400),
+        threads: options.get("threads", num_cpus::get()),
+        hash_mb: options.get("hash", 16),
+    };
+
+    let output = &options.positional[0];
+    match selfplay::play(&params, output) {
+        Ok(stats) => eprintln!("{} games (+{} ={} -{}), {} positions written to {}",
+                               stats.games, stats.white_wins, stats.draws, stats.black_wins, stats.positions, output),
+        Err(e) => exit_with(&format!("failed to write {}: {}", output, e)),
+    }
+}
@@ -0,0 +1,33 @@
+use crate::engine::tune::{TuneParams, Tuner};
+use crate::tools::{exit_with, Options};
+
+const USAGE: &str = "usage: tune <output> <data>... [--iterations N] [--step N] [--skip-plies N] [--max-positions N]";
+
+pub fn run(options: Options) {
+    if options.positional.len() < 2 {
+        exit_with(USAGE);
+    }
+
+    let output = &options.positional[0];
+    let mut tuner = Tuner::new(TuneParams {
+        iterations: options.get("iterations", 100),
+        step: options.get("step", 1),
+        skip_plies: options.get("skip-plies", 16),
+        max_positions: options.get("max-positions", 1_000_000),
+    });
+
+    for path in options.positional[1..].iter() {
+        if let Err(e) = tuner.add_file(path) {
+            exit_with(&format!("failed to read {}: {}", path, e));
+        }
+    }
+
+    if tuner.len() == 0 {
+        exit_with("no positions to tune with");
+    }
+
+    match tuner.run(output) {
+        Ok(_) => eprintln!("tuned parameters written to {}", output),
+        Err(e) => exit_with(&format!("failed to write {}: {}", output, e)),
+    }
+}